@@ -1,9 +1,45 @@
+use std::fmt;
 use std::ops::*;
 
 /// The Bitboard type is a redefined u64 which has added helper functions.
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub struct Bitboard(u64);
 
+pub const FILE_A: Bitboard = Bitboard(0x0101010101010101);
+pub const FILE_H: Bitboard = Bitboard(0x8080808080808080);
+pub const RANK_1: Bitboard = Bitboard(0xFF);
+pub const RANK_8: Bitboard = Bitboard(0xFF00000000000000);
+
+/// A direction on the board, with north being towards the eighth rank and east towards the h-file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    North,
+    South,
+    East,
+    West,
+    NorthEast,
+    NorthWest,
+    SouthEast,
+    SouthWest,
+}
+
+impl Direction {
+    /// How much the index of a square changes when moving one square in this direction
+    #[inline]
+    pub fn offset(self) -> i8 {
+        match self {
+            Direction::North => 8,
+            Direction::South => -8,
+            Direction::East => 1,
+            Direction::West => -1,
+            Direction::NorthEast => 9,
+            Direction::NorthWest => 7,
+            Direction::SouthEast => -7,
+            Direction::SouthWest => -9,
+        }
+    }
+}
+
 impl Bitboard {
     pub fn empty() -> Self {
         Bitboard(0)
@@ -78,54 +114,150 @@ impl Bitboard {
         self.set_bit(sq(r, f));
     }
 
-    /// Shift a piece by some square value something something todo magnitude of 1
-    #[inline]
-    pub fn shift1(self, shift: i8) -> Bitboard {
-        let mut result = self;
-        if shift < 0 {
-            const MASKS: [u64; 2] = [!0x0101010101010101, !0x8080808080808080];
-            let mask = if shift % 8 == 0 {
-                !0
-            } else if shift % 8 == -1 {
-                MASKS[0]
-            } else if shift % 8 == -7 {
-                MASKS[1]
-            } else {
-                panic!("invalid shift of magnitude 1 (not magnitude of 1)");
-            };
-            result.0 = (self.0 & mask).overflowing_shr(-shift as u32).0;
+    /// Return the number of bits set
+    #[inline]
+    pub fn popcount(&self) -> u32 {
+        self.0.count_ones()
+    }
+
+    /// Return the index of the most significant bit. The bitboard must not be empty.
+    #[inline]
+    pub fn msb(&self) -> u32 {
+        debug_assert!(!self.is_empty());
+
+        63 - self.0.leading_zeros()
+    }
+
+    /// Return true if more than one bit is set
+    #[inline]
+    pub fn more_than_one(&self) -> bool {
+        self.0 & self.0.wrapping_sub(1) != 0
+    }
+
+    /// All the squares on a file, where 0 is the a-file
+    #[inline]
+    pub fn file(file: u8) -> Self {
+        debug_assert!(file <= 7);
+
+        Bitboard(FILE_A.0 << file)
+    }
+
+    /// All the squares on a rank, where 0 is the first rank
+    #[inline]
+    pub fn rank(rank: u8) -> Self {
+        debug_assert!(rank <= 7);
+
+        Bitboard(RANK_1.0 << (8 * rank))
+    }
+
+    /// The diagonal going up and to the right through a square, including the square
+    #[inline]
+    pub fn diagonal(sq: u8) -> Self {
+        debug_assert!(sq <= 63);
+
+        // Moving the long a1-h8 diagonal up or down a rank moves it one file across
+        let offset = (sq & 7) as i32 - (sq >> 3) as i32;
+        if offset >= 0 {
+            Bitboard(0x8040201008040201 >> (8 * offset))
+        } else {
+            Bitboard(0x8040201008040201 << (8 * -offset))
+        }
+    }
+
+    /// The diagonal going up and to the left through a square, including the square
+    #[inline]
+    pub fn anti_diagonal(sq: u8) -> Self {
+        debug_assert!(sq <= 63);
+
+        let offset = (sq & 7) as i32 + (sq >> 3) as i32 - 7;
+        if offset >= 0 {
+            Bitboard(0x0102040810204080 << (8 * offset))
         } else {
-            const MASKS: [u64; 2] = [!0x8080808080808080, !0x0101010101010101];
-            let mask = if shift % 8 == 0 {
-                !0
-            } else if shift % 8 == 1 {
-                MASKS[0]
-            } else if shift % 8 == 7 {
-                MASKS[1]
-            } else {
-                panic!("invalid shift of magnitude 1 (not magnitude of 1)");
-            };
-            result.0 = (self.0 & mask).overflowing_shl(shift as u32).0;
+            Bitboard(0x0102040810204080 >> (8 * -offset))
         }
-        result
     }
 
-    pub fn print(self) {
+    /// Move every bit one square in a direction. Bits which would leave the board, including
+    /// off the side, are dropped rather than wrapping around to the other side.
+    #[inline]
+    pub fn shift(self, dir: Direction) -> Bitboard {
+        let bb = self.0;
+        Bitboard(match dir {
+            Direction::North => bb << 8,
+            Direction::South => bb >> 8,
+            Direction::East => (bb & !FILE_H.0) << 1,
+            Direction::West => (bb & !FILE_A.0) >> 1,
+            Direction::NorthEast => (bb & !FILE_H.0) << 9,
+            Direction::NorthWest => (bb & !FILE_A.0) << 7,
+            Direction::SouthEast => (bb & !FILE_H.0) >> 7,
+            Direction::SouthWest => (bb & !FILE_A.0) >> 9,
+        })
+    }
+
+    #[inline]
+    pub fn north(self) -> Bitboard {
+        self.shift(Direction::North)
+    }
+
+    #[inline]
+    pub fn south(self) -> Bitboard {
+        self.shift(Direction::South)
+    }
+
+    #[inline]
+    pub fn east(self) -> Bitboard {
+        self.shift(Direction::East)
+    }
+
+    #[inline]
+    pub fn west(self) -> Bitboard {
+        self.shift(Direction::West)
+    }
+
+    #[inline]
+    pub fn north_east(self) -> Bitboard {
+        self.shift(Direction::NorthEast)
+    }
+
+    #[inline]
+    pub fn north_west(self) -> Bitboard {
+        self.shift(Direction::NorthWest)
+    }
+
+    #[inline]
+    pub fn south_east(self) -> Bitboard {
+        self.shift(Direction::SouthEast)
+    }
+
+    #[inline]
+    pub fn south_west(self) -> Bitboard {
+        self.shift(Direction::SouthWest)
+    }
+
+    pub fn lsb_iter(self) -> LsbIter {
+        LsbIter { bb: self }
+    }
+}
+
+/// Shows the board with the eighth rank at the top, marking set bits with '#'
+impl fmt::Display for Bitboard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         for row in (0..8).rev() {
             for col in 0..8 {
-                if self.0 & (1 << sq(row, col)) != 0 {
-                    print!("#");
-                } else {
-                    print!(".");
-                }
+                let c = if self.is_set(sq(row, col)) { '#' } else { '.' };
+                write!(f, "{}", c)?;
+            }
+            if row > 0 {
+                writeln!(f)?;
             }
-            println!();
         }
-        println!();
+        Ok(())
     }
+}
 
-    pub fn lsb_iter(self) -> LsbIter {
-        LsbIter { bb: self }
+impl fmt::Debug for Bitboard {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Bitboard({:#018x})", self.0)
     }
 }
 
@@ -135,6 +267,12 @@ impl From<u64> for Bitboard {
     }
 }
 
+impl From<Bitboard> for u64 {
+    fn from(b: Bitboard) -> u64 {
+        b.0
+    }
+}
+
 // this should not be here
 #[inline]
 pub fn sq(r: u8, f: u8) -> u8 {
@@ -142,7 +280,7 @@ pub fn sq(r: u8, f: u8) -> u8 {
 }
 
 macro_rules! bitboard_operation {
-    ($i:ty, $j:ty, $f:ident) => {
+    ($i:ident, $f:ident, $assign:ident, $assign_f:ident) => {
         impl $i for Bitboard {
             type Output = Self;
 
@@ -151,25 +289,35 @@ macro_rules! bitboard_operation {
             }
         }
 
-        impl $j for Bitboard {
+        impl $i<u64> for Bitboard {
             type Output = Self;
 
             fn $f(self, rhs: u64) -> Self::Output {
                 Self(self.0.$f(rhs))
             }
         }
+
+        impl $assign for Bitboard {
+            fn $assign_f(&mut self, rhs: Self) {
+                self.0.$assign_f(rhs.0);
+            }
+        }
+
+        impl $assign<u64> for Bitboard {
+            fn $assign_f(&mut self, rhs: u64) {
+                self.0.$assign_f(rhs);
+            }
+        }
     };
 }
 
-// TODO add assigning operators
-
-bitboard_operation!(Add, Add<u64>, add);
-bitboard_operation!(BitAnd, BitAnd<u64>, bitand);
-bitboard_operation!(BitOr, BitOr<u64>, bitor);
-bitboard_operation!(BitXor, BitXor<u64>, bitxor);
-bitboard_operation!(Shl, Shl<u64>, shl);
-bitboard_operation!(Shr, Shr<u64>, shr);
-bitboard_operation!(Sub, Sub<u64>, sub);
+bitboard_operation!(Add, add, AddAssign, add_assign);
+bitboard_operation!(BitAnd, bitand, BitAndAssign, bitand_assign);
+bitboard_operation!(BitOr, bitor, BitOrAssign, bitor_assign);
+bitboard_operation!(BitXor, bitxor, BitXorAssign, bitxor_assign);
+bitboard_operation!(Shl, shl, ShlAssign, shl_assign);
+bitboard_operation!(Shr, shr, ShrAssign, shr_assign);
+bitboard_operation!(Sub, sub, SubAssign, sub_assign);
 
 impl Not for Bitboard {
     type Output = Self;
@@ -178,6 +326,76 @@ impl Not for Bitboard {
     }
 }
 
+/// The squares strictly between two squares on the same rank, file or diagonal, or nothing if
+/// they don't share a line
+#[inline]
+pub fn between(a: u8, b: u8) -> Bitboard {
+    Bitboard(BETWEEN[a as usize][b as usize])
+}
+
+/// The whole line across the board through two squares, including both of them, or nothing if
+/// they don't share a line
+#[inline]
+pub fn line(a: u8, b: u8) -> Bitboard {
+    Bitboard(LINE[a as usize][b as usize])
+}
+
+static BETWEEN: [[u64; 64]; 64] = gen_line_table(false);
+static LINE: [[u64; 64]; 64] = gen_line_table(true);
+
+/// The squares a slider reaches from a square moving in one direction on an empty board
+const fn ray(sq: u8, file_step: i8, rank_step: i8) -> u64 {
+    let mut ray = 0;
+    let mut file = (sq & 7) as i8 + file_step;
+    let mut rank = (sq >> 3) as i8 + rank_step;
+    while file >= 0 && file < 8 && rank >= 0 && rank < 8 {
+        ray |= 1 << (rank * 8 + file);
+        file += file_step;
+        rank += rank_step;
+    }
+    ray
+}
+
+/// Fill the table of either the whole lines or the squares between every pair of squares, by
+/// walking out from each square in every direction
+const fn gen_line_table(whole_line: bool) -> [[u64; 64]; 64] {
+    const STEPS: [(i8, i8); 8] = [
+        (0, 1),
+        (0, -1),
+        (1, 0),
+        (-1, 0),
+        (1, 1),
+        (-1, 1),
+        (1, -1),
+        (-1, -1),
+    ];
+    let mut table = [[0; 64]; 64];
+
+    let mut a = 0;
+    while a < 64 {
+        let mut i = 0;
+        while i < STEPS.len() {
+            let (file_step, rank_step) = STEPS[i];
+            let full = ray(a, file_step, rank_step) | ray(a, -file_step, -rank_step) | 1 << a;
+
+            let mut between = 0;
+            let mut file = (a & 7) as i8 + file_step;
+            let mut rank = (a >> 3) as i8 + rank_step;
+            while file >= 0 && file < 8 && rank >= 0 && rank < 8 {
+                let b = (rank * 8 + file) as usize;
+                table[a as usize][b] = if whole_line { full } else { between };
+                between |= 1 << b;
+                file += file_step;
+                rank += rank_step;
+            }
+            i += 1;
+        }
+        a += 1;
+    }
+
+    table
+}
+
 // LSB iterator
 // Iterate over the index of each bit starting from the least significant bit
 // I'll do proper docs later
@@ -200,6 +418,7 @@ impl Iterator for LsbIter {
 mod tests {
     use crate::bitboards::*;
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_bit_bounds() {
         assert_eq!(Bitboard(0).is_set(68), false);
     }
@@ -208,4 +427,120 @@ mod tests {
     fn bit_and() {
         assert_eq!(Bitboard(13) & Bitboard(7), Bitboard(5));
     }
+
+    #[test]
+    fn assigning_operators() {
+        let mut bb = Bitboard(0b1100);
+        bb |= Bitboard(0b0011);
+        assert_eq!(bb, Bitboard(0b1111));
+        bb &= 0b0110;
+        assert_eq!(bb, Bitboard(0b0110));
+        bb ^= Bitboard(0b0100);
+        assert_eq!(bb, Bitboard(0b0010));
+        bb <<= 3;
+        assert_eq!(bb, Bitboard(0b10000));
+    }
+
+    #[test]
+    fn counting() {
+        let bb = Bitboard::square(3) | Bitboard::square(41) | Bitboard::square(63);
+        assert_eq!(bb.popcount(), 3);
+        assert_eq!(bb.lsb(), 3);
+        assert_eq!(bb.msb(), 63);
+        assert!(bb.more_than_one());
+        assert!(!Bitboard::square(41).more_than_one());
+        assert!(!Bitboard::empty().more_than_one());
+    }
+
+    #[test]
+    fn masks() {
+        assert_eq!(Bitboard::file(0), FILE_A);
+        assert_eq!(Bitboard::file(7), FILE_H);
+        assert_eq!(Bitboard::rank(0), RANK_1);
+        assert_eq!(Bitboard::rank(7), RANK_8);
+        assert_eq!(Bitboard::file(4).popcount(), 8);
+
+        // c1 is on the diagonal c1-h6 and the anti-diagonal c1-a3
+        assert_eq!(
+            Bitboard::diagonal(2),
+            Bitboard::square(2)
+                | Bitboard::square(11)
+                | Bitboard::square(20)
+                | Bitboard::square(29)
+                | Bitboard::square(38)
+                | Bitboard::square(47)
+        );
+        assert_eq!(
+            Bitboard::anti_diagonal(2),
+            Bitboard::square(2) | Bitboard::square(9) | Bitboard::square(16)
+        );
+        assert_eq!(Bitboard::diagonal(7), Bitboard::square(7));
+        assert_eq!(Bitboard::anti_diagonal(56), Bitboard::anti_diagonal(7));
+        for sq in 0..64 {
+            assert!(Bitboard::diagonal(sq).is_set(sq));
+            assert!(Bitboard::anti_diagonal(sq).is_set(sq));
+        }
+    }
+
+    #[test]
+    fn shifts() {
+        // Bits on the edge fall off instead of wrapping around
+        let corners = Bitboard::square(0) | Bitboard::square(7) | Bitboard::square(56);
+        assert_eq!(corners.north(), Bitboard::square(8) | Bitboard::square(15));
+        assert_eq!(corners.south(), Bitboard::empty() | Bitboard::square(48));
+        assert_eq!(corners.east(), Bitboard::square(1) | Bitboard::square(57));
+        assert_eq!(corners.west(), Bitboard::square(6));
+        assert_eq!(corners.north_east(), Bitboard::square(9));
+        assert_eq!(corners.north_west(), Bitboard::square(14));
+        assert_eq!(corners.south_east(), Bitboard::square(49));
+        assert_eq!(corners.south_west(), Bitboard::empty());
+
+        for dir in [Direction::North, Direction::SouthEast, Direction::West] {
+            let moved = Bitboard::square(27).shift(dir);
+            assert_eq!(moved, Bitboard::square((27 + dir.offset()) as u8));
+        }
+    }
+
+    #[test]
+    fn between_and_line() {
+        // a1 and h8 share the long diagonal
+        assert_eq!(
+            between(0, 63),
+            Bitboard::diagonal(0) & !Bitboard(1 | 1 << 63)
+        );
+        assert_eq!(line(0, 63), Bitboard::diagonal(0));
+        assert_eq!(between(63, 0), between(0, 63));
+        // b1 and b4 share the b-file
+        assert_eq!(between(1, 25), Bitboard::square(9) | Bitboard::square(17));
+        assert_eq!(line(25, 1), Bitboard::file(1));
+        // e1 and h1 share the first rank, with f1 and g1 in between
+        assert_eq!(between(4, 7), Bitboard::square(5) | Bitboard::square(6));
+        assert_eq!(line(4, 7), RANK_1);
+        // Neighbouring squares have nothing between them but still share a line
+        assert!(between(27, 36).is_empty());
+        assert_eq!(line(27, 36), Bitboard::diagonal(27));
+        assert_eq!(line(28, 35), Bitboard::anti_diagonal(28));
+        // a1 and b3 don't share a line
+        assert!(between(0, 17).is_empty());
+        assert!(line(0, 17).is_empty());
+        assert!(line(5, 5).is_empty());
+    }
+
+    #[test]
+    fn display() {
+        let bb = Bitboard::square(0) | Bitboard::square(9) | Bitboard::square(63);
+        assert_eq!(
+            bb.to_string(),
+            "\
+.......#
+........
+........
+........
+........
+........
+.#......
+#......."
+        );
+        assert_eq!(format!("{:?}", bb), "Bitboard(0x8000000000000201)");
+    }
 }
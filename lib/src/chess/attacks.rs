@@ -1,4 +1,14 @@
-use crate::bitboards::Bitboard;
+use super::types::ChessSide;
+use crate::bitboards::{Bitboard, Direction};
+#[cfg(all(feature = "bmi2", target_arch = "x86_64"))]
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+/// The squares a pawn of the given side attacks from a square
+#[inline]
+pub fn get_pawn_attacks(sq: u8, side: ChessSide) -> Bitboard {
+    PAWN_ATTACKS[side as usize][sq as usize].into()
+}
 
 #[inline]
 pub fn get_knight_attacks(sq: u8) -> Bitboard {
@@ -12,12 +22,14 @@ pub fn get_king_attacks(sq: u8) -> Bitboard {
 
 #[inline]
 pub fn get_bishop_attacks(sq: u8, occ: Bitboard) -> Bitboard {
-    gen_sliding_attack(sq, occ, [-9, -7, 7, 9])
+    let tables = slider_tables();
+    tables.lookup(&tables.bishop[sq as usize], occ)
 }
 
 #[inline]
 pub fn get_rook_attacks(sq: u8, occ: Bitboard) -> Bitboard {
-    gen_sliding_attack(sq, occ, [-8, -1, 1, 8])
+    let tables = slider_tables();
+    tables.lookup(&tables.rook[sq as usize], occ)
 }
 
 #[inline]
@@ -25,9 +37,31 @@ pub fn get_queen_attacks(sq: u8, occ: Bitboard) -> Bitboard {
     get_bishop_attacks(sq, occ) | get_rook_attacks(sq, occ)
 }
 
+const PAWN_ATTACKS: [[u64; 64]; 2] = gen_pawn_attack_table();
 const KNIGHT_ATTACKS: [u64; 64] = gen_knight_attack_table();
 const KING_ATTACKS: [u64; 64] = gen_king_attack_table();
 
+const fn gen_pawn_attack_table() -> [[u64; 64]; 2] {
+    let mut attacks = [[0; 64]; 2];
+
+    let mut sq = 0;
+    loop {
+        if sq >= 64 {
+            break;
+        }
+        let attack = 1 << sq;
+
+        attacks[0][sq] |= (attack & !0x0101010101010101) << 7;
+        attacks[0][sq] |= (attack & !0x8080808080808080) << 9;
+        attacks[1][sq] |= (attack & !0x8080808080808080) >> 7;
+        attacks[1][sq] |= (attack & !0x0101010101010101) >> 9;
+
+        sq += 1;
+    }
+
+    attacks
+}
+
 const fn gen_knight_attack_table() -> [u64; 64] {
     let mut attacks = [0; 64];
 
@@ -78,13 +112,304 @@ const fn gen_king_attack_table() -> [u64; 64] {
     attacks
 }
 
-fn gen_sliding_attack(sq: u8, _occ: Bitboard, dirs: [i8; 4]) -> Bitboard {
+const BISHOP_DIRS: [Direction; 4] = [
+    Direction::SouthWest,
+    Direction::SouthEast,
+    Direction::NorthWest,
+    Direction::NorthEast,
+];
+const ROOK_DIRS: [Direction; 4] = [
+    Direction::South,
+    Direction::West,
+    Direction::East,
+    Direction::North,
+];
+
+/// The magic number and attack table locations for a slider on one square
+#[derive(Clone, Copy, Default)]
+struct Magic {
+    // The squares whose occupancy affects the attacks, which excludes the edges of the board
+    mask: u64,
+    magic: u64,
+    shift: u32,
+    // Where this square's attacks start in the shared attack table
+    offset: usize,
+    // Where this square's attacks start in the PEXT indexed attack table
+    #[cfg(all(feature = "bmi2", target_arch = "x86_64"))]
+    pext_offset: usize,
+}
+
+/// Fancy magic bitboard tables for bishops and rooks, which share one attack table. With the
+/// `bmi2` feature there is also a table indexed with PEXT, which is used instead when the CPU
+/// supports it.
+struct SliderTables {
+    bishop: [Magic; 64],
+    rook: [Magic; 64],
+    attacks: Vec<u64>,
+    #[cfg(all(feature = "bmi2", target_arch = "x86_64"))]
+    pext_attacks: Vec<u64>,
+    #[cfg(all(feature = "bmi2", target_arch = "x86_64"))]
+    use_pext: AtomicBool,
+}
+
+/// The ways slider attacks can be looked up
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SliderBackend {
+    /// Tables indexed by multiplying the occupancy with a magic number, which works everywhere
+    Magic,
+    /// Tables indexed with the PEXT instruction, which needs the `bmi2` feature and a CPU with
+    /// BMI2
+    Pext,
+}
+
+/// The backend slider attacks are looked up with. PEXT is used when it is available.
+pub fn slider_backend() -> SliderBackend {
+    #[cfg(all(feature = "bmi2", target_arch = "x86_64"))]
+    if slider_tables().use_pext.load(Ordering::Relaxed) {
+        return SliderBackend::Pext;
+    }
+    SliderBackend::Magic
+}
+
+/// Switch the backend slider attacks are looked up with, on every thread, which is mainly useful
+/// for comparing them. Returns false and leaves the backend alone if it isn't available.
+pub fn set_slider_backend(backend: SliderBackend) -> bool {
+    match backend {
+        SliderBackend::Magic => {
+            #[cfg(all(feature = "bmi2", target_arch = "x86_64"))]
+            slider_tables().use_pext.store(false, Ordering::Relaxed);
+            true
+        }
+        SliderBackend::Pext => {
+            #[cfg(all(feature = "bmi2", target_arch = "x86_64"))]
+            if pext_supported() {
+                slider_tables().use_pext.store(true, Ordering::Relaxed);
+                return true;
+            }
+            false
+        }
+    }
+}
+
+#[cfg(all(feature = "bmi2", target_arch = "x86_64"))]
+fn pext_supported() -> bool {
+    std::arch::is_x86_feature_detected!("bmi2")
+}
+
+impl Magic {
+    #[inline]
+    fn index(&self, occ: u64) -> usize {
+        ((occ & self.mask).wrapping_mul(self.magic) >> self.shift) as usize
+    }
+}
+
+impl SliderTables {
+    #[inline]
+    fn lookup(&self, magic: &Magic, occ: Bitboard) -> Bitboard {
+        #[cfg(all(feature = "bmi2", target_arch = "x86_64"))]
+        if self.use_pext.load(Ordering::Relaxed) {
+            // Safety: PEXT is only switched on when the CPU supports BMI2
+            return unsafe { self.lookup_pext(magic, occ) };
+        }
+        self.lookup_magic(magic, occ)
+    }
+
+    #[inline]
+    fn lookup_magic(&self, magic: &Magic, occ: Bitboard) -> Bitboard {
+        self.attacks[magic.offset + magic.index(occ.into())].into()
+    }
+
+    /// Look up attacks in the PEXT indexed table. This can only be inlined when compiling for a
+    /// CPU with BMI2, e.g. with RUSTFLAGS="-C target-cpu=native", and is a function call
+    /// otherwise.
+    ///
+    /// # Safety
+    ///
+    /// The CPU must support BMI2.
+    #[cfg(all(feature = "bmi2", target_arch = "x86_64"))]
+    #[target_feature(enable = "bmi2")]
+    #[inline]
+    unsafe fn lookup_pext(&self, magic: &Magic, occ: Bitboard) -> Bitboard {
+        let index = std::arch::x86_64::_pext_u64(occ.into(), magic.mask) as usize;
+        self.pext_attacks[magic.pext_offset + index].into()
+    }
+}
+
+static SLIDER_TABLES: OnceLock<SliderTables> = OnceLock::new();
+
+#[inline]
+fn slider_tables() -> &'static SliderTables {
+    SLIDER_TABLES.get_or_init(|| {
+        let mut attacks = Vec::new();
+        let bishop = init_slider(BISHOP_DIRS, &mut attacks);
+        let rook = init_slider(ROOK_DIRS, &mut attacks);
+        let tables = SliderTables {
+            bishop,
+            rook,
+            attacks,
+            #[cfg(all(feature = "bmi2", target_arch = "x86_64"))]
+            pext_attacks: Vec::new(),
+            #[cfg(all(feature = "bmi2", target_arch = "x86_64"))]
+            use_pext: AtomicBool::new(false),
+        };
+        #[cfg(all(feature = "bmi2", target_arch = "x86_64"))]
+        if pext_supported() {
+            // Safety: the CPU supports BMI2
+            return unsafe { with_pext(tables) };
+        }
+        tables
+    })
+}
+
+/// A small xorshift generator so that the magic search is deterministic
+struct Xorshift(u64);
+
+/// Seeds for each rank which are known to find magics quickly
+const MAGIC_SEEDS: [u64; 8] = [728, 10316, 55013, 32803, 12281, 15100, 16645, 255];
+
+impl Xorshift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(2685821657736338717)
+    }
+
+    /// Magics with few bits set are much more likely to work
+    fn sparse(&mut self) -> u64 {
+        self.next() & self.next() & self.next()
+    }
+}
+
+/// Set up the lookup for every square for a slider moving in the given directions, appending
+/// each square's attacks to the shared attack table
+fn init_slider(dirs: [Direction; 4], attacks: &mut Vec<u64>) -> [Magic; 64] {
+    let mut magics = [Magic::default(); 64];
+    let mut occupancies = Vec::new();
+    let mut references = Vec::new();
+
+    for sq in 0..64 {
+        // Pieces on the edge of the board never block anything, unless the slider is on that edge
+        let rank_edges = 0xFF000000000000FF & !(0xFF << (sq & !7));
+        let file_edges = 0x8181818181818181 & !(0x0101010101010101 << (sq & 7));
+        let mask =
+            u64::from(gen_sliding_attack(sq, Bitboard::empty(), dirs)) & !(rank_edges | file_edges);
+        let shift = 64 - mask.count_ones();
+
+        // Enumerate every subset of the mask with the Carry-Rippler trick
+        occupancies.clear();
+        references.clear();
+        let mut occ = 0u64;
+        loop {
+            occupancies.push(occ);
+            references.push(u64::from(gen_sliding_attack(sq, occ.into(), dirs)));
+            occ = occ.wrapping_sub(mask) & mask;
+            if occ == 0 {
+                break;
+            }
+        }
+
+        let offset = attacks.len();
+        attacks.resize(offset + occupancies.len(), 0);
+        let table = &mut attacks[offset..];
+        let magic = find_magic(sq, mask, shift, &occupancies, &references, table);
+
+        magics[sq as usize] = Magic {
+            mask,
+            magic,
+            shift,
+            offset,
+            #[cfg(all(feature = "bmi2", target_arch = "x86_64"))]
+            pext_offset: 0,
+        };
+    }
+
+    magics
+}
+
+/// Fill the PEXT indexed attack table from the magic one, which holds every attack set already,
+/// and switch to it
+///
+/// # Safety
+///
+/// The CPU must support BMI2.
+#[cfg(all(feature = "bmi2", target_arch = "x86_64"))]
+#[target_feature(enable = "bmi2")]
+unsafe fn with_pext(mut tables: SliderTables) -> SliderTables {
+    let mut pext_attacks = Vec::with_capacity(tables.attacks.len());
+    for magic in tables.bishop.iter_mut().chain(tables.rook.iter_mut()) {
+        magic.pext_offset = pext_attacks.len();
+        pext_attacks.resize(pext_attacks.len() + (1 << magic.mask.count_ones()), 0);
+        let mut occ = 0u64;
+        loop {
+            let index = std::arch::x86_64::_pext_u64(occ, magic.mask) as usize;
+            pext_attacks[magic.pext_offset + index] =
+                tables.attacks[magic.offset + magic.index(occ)];
+            occ = occ.wrapping_sub(magic.mask) & magic.mask;
+            if occ == 0 {
+                break;
+            }
+        }
+    }
+    tables.pext_attacks = pext_attacks;
+    tables.use_pext = AtomicBool::new(true);
+    tables
+}
+
+/// Search for a magic which maps every occupancy to an index holding its attacks, filling the
+/// table as we go
+fn find_magic(
+    sq: u8,
+    mask: u64,
+    shift: u32,
+    occupancies: &[u64],
+    references: &[u64],
+    table: &mut [u64],
+) -> u64 {
+    // Each attempt marks the entries it fills with its epoch, so the table doesn't need
+    // clearing between attempts
+    let mut epochs = vec![0u32; occupancies.len()];
+    let mut epoch = 0;
+    let mut rng = Xorshift(MAGIC_SEEDS[(sq >> 3) as usize]);
+    loop {
+        let magic = rng.sparse();
+        if (mask.wrapping_mul(magic) >> 56).count_ones() < 6 {
+            continue;
+        }
+
+        epoch += 1;
+        let works = occupancies
+            .iter()
+            .zip(references)
+            .all(|(&occ, &reference)| {
+                let index = (occ.wrapping_mul(magic) >> shift) as usize;
+                if epochs[index] < epoch {
+                    epochs[index] = epoch;
+                    table[index] = reference;
+                    true
+                } else {
+                    table[index] == reference
+                }
+            });
+        if works {
+            return magic;
+        }
+    }
+}
+
+/// Walk each ray of a slider until it hits a blocker. This is slow, so it is only used to fill
+/// the magic tables and to check them in tests.
+fn gen_sliding_attack(sq: u8, occ: Bitboard, dirs: [Direction; 4]) -> Bitboard {
     let mut attack = Bitboard::empty();
     for dir in dirs {
-        let mut sqbb = Bitboard::square(sq);
+        let mut sqbb = Bitboard::square(sq).shift(dir);
+        // Walk along the ray until we fall off the board or hit a blocker, which is attacked too
         while !sqbb.is_empty() {
-            sqbb = sqbb.shift1(dir);
-            attack = attack | sqbb;
+            attack |= sqbb;
+            if !(sqbb & occ).is_empty() {
+                break;
+            }
+            sqbb = sqbb.shift(dir);
         }
     }
     attack
@@ -93,9 +418,95 @@ fn gen_sliding_attack(sq: u8, _occ: Bitboard, dirs: [i8; 4]) -> Bitboard {
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    #[test]
+    fn pawn_attacks() {
+        // a2 and h7 are on the edge, so the pawns only attack one square
+        assert_eq!(get_pawn_attacks(8, ChessSide::White), Bitboard::square(17));
+        assert_eq!(get_pawn_attacks(55, ChessSide::Black), Bitboard::square(46));
+        assert_eq!(
+            get_pawn_attacks(28, ChessSide::White),
+            Bitboard::square(35) | Bitboard::square(37)
+        );
+        assert_eq!(
+            get_pawn_attacks(28, ChessSide::Black),
+            Bitboard::square(19) | Bitboard::square(21)
+        );
+        // Pawns on the last rank attack nothing
+        assert!(get_pawn_attacks(60, ChessSide::White).is_empty());
+        assert!(get_pawn_attacks(3, ChessSide::Black).is_empty());
+    }
     #[test]
     fn bishop_attacks() {
-        get_queen_attacks(20, Bitboard::empty()).print();
-        //assert!(false);
+        // A queen on e3 on an empty board
+        assert_eq!(
+            get_queen_attacks(20, Bitboard::empty()).to_string(),
+            "\
+....#...
+#...#...
+.#..#..#
+..#.#.#.
+...###..
+####.###
+...###..
+..#.#.#."
+        );
+    }
+
+    #[test]
+    fn blocked_slider_attacks() {
+        // A rook on a1 blocked by pieces on a3 and c1
+        let occ = Bitboard::square(16) | Bitboard::square(2);
+        assert_eq!(
+            get_rook_attacks(0, occ),
+            Bitboard::square(8) | Bitboard::square(16) | Bitboard::square(1) | Bitboard::square(2)
+        );
+
+        // A bishop on d4 blocked by a piece on f6
+        let occ = Bitboard::square(45);
+        let attacks = get_bishop_attacks(27, occ);
+        assert!(attacks.is_set(36));
+        assert!(attacks.is_set(45));
+        assert!(!attacks.is_set(54));
+        assert!(attacks.is_set(0));
+        assert!(attacks.is_set(48));
+        assert!(attacks.is_set(6));
+    }
+
+    #[test]
+    fn slider_tables_match_ray_walker() {
+        for sq in 0..64 {
+            for (dirs, magic) in [
+                (BISHOP_DIRS, &slider_tables().bishop[sq as usize]),
+                (ROOK_DIRS, &slider_tables().rook[sq as usize]),
+            ] {
+                let mut occ = 0u64;
+                loop {
+                    // Squares outside the mask should make no difference
+                    let noise = !magic.mask & 0x00FF00FF00FF00FF;
+                    for full_occ in [occ, occ | noise] {
+                        let expected = gen_sliding_attack(sq, full_occ.into(), dirs);
+                        assert_eq!(
+                            slider_tables().lookup_magic(magic, full_occ.into()),
+                            expected,
+                            "square {} occupancy {:#x}",
+                            sq,
+                            full_occ
+                        );
+                        #[cfg(all(feature = "bmi2", target_arch = "x86_64"))]
+                        if pext_supported() {
+                            // Safety: the CPU supports BMI2
+                            let pext =
+                                unsafe { slider_tables().lookup_pext(magic, full_occ.into()) };
+                            assert_eq!(pext, expected, "square {} occupancy {:#x}", sq, full_occ);
+                        }
+                    }
+                    occ = occ.wrapping_sub(magic.mask) & magic.mask;
+                    if occ == 0 {
+                        break;
+                    }
+                }
+            }
+        }
     }
 }
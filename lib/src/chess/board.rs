@@ -1,11 +1,30 @@
+use super::attacks::get_pawn_attacks;
+use super::eval::PsqState;
+use super::fen::{parse_fen, write_fen, FenError};
+use super::zobrist::KEYS;
 use super::{movegen::*, types::*};
 use crate::{bitboards::*, Board};
+use std::fmt;
 
 // (File, Rank)
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub struct Square(u8, u8);
 
 impl Square {
+    pub fn new(file: u8, rank: u8) -> Self {
+        debug_assert!(file <= 7 && rank <= 7);
+
+        Square(file, rank)
+    }
+
+    /// Parse a square in algebraic notation, such as "e4"
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.as_bytes() {
+            &[file @ b'a'..=b'h', rank @ b'1'..=b'8'] => Some(Square(file - b'a', rank - b'1')),
+            _ => None,
+        }
+    }
+
     pub fn index(&self) -> u8 {
         self.0 + 8 * self.1
     }
@@ -14,11 +33,22 @@ impl Square {
         Square(idx & 0b111, (idx >> 3) & 0b111)
     }
 
+    pub fn file(&self) -> u8 {
+        self.0
+    }
+
     pub fn rank(&self) -> u8 {
         self.1
     }
 }
 
+impl fmt::Display for Square {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", (b'a' + self.0) as char, self.1 + 1)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum CastlingRight {
     WhiteKing,
     WhiteQueen,
@@ -26,40 +56,138 @@ pub enum CastlingRight {
     BlackQueen,
 }
 
-#[derive(Default)]
-pub struct CastlingRights(u8);
+impl CastlingRight {
+    /// The right of a side to castle on the king or queen side
+    pub fn new(side: ChessSide, king_side: bool) -> Self {
+        match (side, king_side) {
+            (ChessSide::White, true) => CastlingRight::WhiteKing,
+            (ChessSide::White, false) => CastlingRight::WhiteQueen,
+            (ChessSide::Black, true) => CastlingRight::BlackKing,
+            (ChessSide::Black, false) => CastlingRight::BlackQueen,
+        }
+    }
+
+    pub fn side(self) -> ChessSide {
+        match self {
+            CastlingRight::WhiteKing | CastlingRight::WhiteQueen => ChessSide::White,
+            CastlingRight::BlackKing | CastlingRight::BlackQueen => ChessSide::Black,
+        }
+    }
+
+    pub fn is_king_side(self) -> bool {
+        matches!(self, CastlingRight::WhiteKing | CastlingRight::BlackKing)
+    }
+}
+
+/// The castling rights of both sides. Each right remembers the file of the rook it castles with,
+/// since in Chess960 the rooks don't have to start in the corners.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CastlingRights {
+    // One bit for each right, in the order of `CastlingRight`
+    mask: u8,
+    rook_files: [u8; 4],
+}
 
 impl CastlingRights {
+    /// Give a right to castle with the rook in the corner, as in standard chess
     pub fn set_right(&mut self, right: CastlingRight) {
-        self.0 |= 1
-            << match right {
-                CastlingRight::WhiteKing => 0,
-                CastlingRight::WhiteQueen => 1,
-                CastlingRight::BlackKing => 2,
-                CastlingRight::BlackQueen => 3,
-            }
+        self.set_right_with_rook(right, if right.is_king_side() { 7 } else { 0 });
     }
-    pub fn _unset_right(&mut self, right: CastlingRight) {
-        self.0 &= !(1
-            << match right {
-                CastlingRight::WhiteKing => 0,
-                CastlingRight::WhiteQueen => 1,
-                CastlingRight::BlackKing => 2,
-                CastlingRight::BlackQueen => 3,
-            })
+
+    /// Give a right to castle with the rook on the given file
+    pub fn set_right_with_rook(&mut self, right: CastlingRight, file: u8) {
+        self.mask |= 1 << right as u8;
+        self.rook_files[right as usize] = file;
     }
+
+    pub fn unset_right(&mut self, right: CastlingRight) {
+        self.mask &= !(1 << right as u8);
+        self.rook_files[right as usize] = 0;
+    }
+
     pub fn has_right(&self, right: CastlingRight) -> bool {
-        self.0
-            & (1 << match right {
-                CastlingRight::WhiteKing => 0,
-                CastlingRight::WhiteQueen => 1,
-                CastlingRight::BlackKing => 2,
-                CastlingRight::BlackQueen => 3,
-            })
-            != 0
+        self.mask & (1 << right as u8) != 0
     }
+
+    /// The square of the rook a right castles with, if the right is held
+    pub fn rook_square(&self, right: CastlingRight) -> Option<Square> {
+        let rank = match right.side() {
+            ChessSide::White => 0,
+            ChessSide::Black => 7,
+        };
+        self.has_right(right)
+            .then(|| Square::new(self.rook_files[right as usize], rank))
+    }
+
+    /// Remove both rights of a side, which happens when its king moves
+    pub fn unset_side(&mut self, side: ChessSide) {
+        self.unset_right(CastlingRight::new(side, true));
+        self.unset_right(CastlingRight::new(side, false));
+    }
+
+    /// Remove the right to castle with a rook on the given square, which is lost when the rook
+    /// moves or is captured
+    pub fn update_for_square(&mut self, sq: u8) {
+        for right in ALL_CASTLING_RIGHTS {
+            if self.rook_square(right).map(|rook| rook.index()) == Some(sq) {
+                self.unset_right(right);
+            }
+        }
+    }
+
+    /// A number from 0 to 15 saying which rights are held
+    #[inline]
+    pub(super) fn index(&self) -> usize {
+        self.mask as usize
+    }
+}
+
+pub(super) const ALL_CASTLING_RIGHTS: [CastlingRight; 4] = [
+    CastlingRight::WhiteKing,
+    CastlingRight::WhiteQueen,
+    CastlingRight::BlackKing,
+    CastlingRight::BlackQueen,
+];
+
+/// The state which can not be recovered from a move alone, kept so that moves can be unmade
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct UndoState {
+    captured: Piece,
+    castled: bool,
+    castling_rights: CastlingRights,
+    en_passant: Option<Square>,
+    halfmove_clock: u32,
+    hash: u64,
+    check_info: CheckInfo,
 }
 
+/// Checks and pins in a position, which legal move generation and working out whether a move
+/// gives check both need. They are computed whenever a position is set up or a move is made.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) struct CheckInfo {
+    // Enemy pieces giving check to the king of the side to move
+    pub(super) checkers: Bitboard,
+    // For each side, the pieces of either side which are the only piece between the king of that
+    // side and an enemy slider
+    pub(super) blockers_for_king: [Bitboard; SIDE_COUNT],
+    // For each side, its sliders which pin an enemy piece to the enemy king
+    pub(super) pinners: [Bitboard; SIDE_COUNT],
+    // For each piece type, the squares a piece of the side to move would give check from
+    pub(super) check_squares: [Bitboard; PIECE_TYPE_COUNT],
+}
+
+impl CheckInfo {
+    fn empty() -> CheckInfo {
+        CheckInfo {
+            checkers: Bitboard::empty(),
+            blockers_for_king: [Bitboard::empty(); SIDE_COUNT],
+            pinners: [Bitboard::empty(); SIDE_COUNT],
+            check_squares: [Bitboard::empty(); PIECE_TYPE_COUNT],
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ChessBoard {
     // Array which stores each piece
     pub grid: [Piece; 64],
@@ -75,146 +203,408 @@ pub struct ChessBoard {
     pub castling_rights: CastlingRights,
     // En passant
     pub en_passant: Option<Square>,
-    // 50 move rule counter
-    // Full move count
+    // Whether this is a game of Chess960, which changes how castling is written in UCI and FEN
+    pub chess960: bool,
+    // 50 move rule counter, the number of halfmoves since the last capture or pawn move
+    pub halfmove_clock: u32,
+    // Full move count, starting at 1 and incremented after black moves
+    pub fullmove_number: u32,
+
+    // Zobrist hash of the position, updated as moves are made
+    hash: u64,
+    // Checks and pins in the position
+    pub(super) check_info: CheckInfo,
+    // Material and piece-square scores, updated as pieces move
+    pub(super) psq: PsqState,
+
+    // Irreversible state of every move made so far, used to unmake moves
+    undo_stack: Vec<UndoState>,
 }
 
 impl Board for ChessBoard {
     type Move = ChessMove;
+    type FenError = FenError;
+
+    fn make_move(&mut self, mv: ChessMove) {
+        let from = mv.from().index();
+        let to = mv.to().index();
+        let us = self.turn;
+
+        let hash = self.hash;
+        // Take out the parts of the hash which depend on the whole position, before it changes
+        self.hash ^= self.en_passant_key() ^ KEYS.castling[self.castling_rights.index()];
+
+        let castled = self.is_castling(mv);
+        let piece = self.remove_piece(from);
+
+        let captured = if castled {
+            Piece::None
+        } else if mv.is_en_passant() {
+            self.remove_piece(en_passant_capture_square(to, us))
+        } else if self.grid[to as usize] != Piece::None {
+            self.remove_piece(to)
+        } else {
+            Piece::None
+        };
+
+        self.undo_stack.push(UndoState {
+            captured,
+            castled,
+            castling_rights: self.castling_rights,
+            en_passant: self.en_passant,
+            halfmove_clock: self.halfmove_clock,
+            hash,
+            check_info: self.check_info,
+        });
 
-    fn make_move(&mut self, _mv: ChessMove) {
-        unimplemented!()
+        if castled {
+            // Castling is written as the king capturing its own rook. Both are taken off the
+            // board before either is put back, since they can land on each other's squares.
+            let (king_to, rook_to) = castling_destinations(from, to);
+            let rook = self.remove_piece(to);
+            self.set_piece(piece, king_to);
+            self.set_piece(rook, rook_to);
+        } else {
+            match mv.promotion() {
+                None => self.set_piece(piece, to),
+                Some(promotion) => self.set_piece(Piece::from((promotion, us)), to),
+            }
+        }
+
+        if PieceType::from(piece) == PieceType::King {
+            self.castling_rights.unset_side(us);
+        }
+        self.castling_rights.update_for_square(from);
+        self.castling_rights.update_for_square(to);
+
+        self.en_passant = if PieceType::from(piece) == PieceType::Pawn && from.abs_diff(to) == 16 {
+            Some(Square::from_index((from + to) / 2))
+        } else {
+            None
+        };
+
+        if PieceType::from(piece) == PieceType::Pawn || captured != Piece::None {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+        if us == ChessSide::Black {
+            self.fullmove_number += 1;
+        }
+
+        self.turn = !self.turn;
+
+        self.hash ^=
+            KEYS.side ^ self.en_passant_key() ^ KEYS.castling[self.castling_rights.index()];
+        self.update_check_info();
+    }
+
+    fn unmake_move(&mut self, mv: ChessMove) {
+        let state = self
+            .undo_stack
+            .pop()
+            .expect("Attempted to unmake a move when no moves have been made");
+
+        let from = mv.from().index();
+        let to = mv.to().index();
+        self.turn = !self.turn;
+        let us = self.turn;
+
+        if state.castled {
+            let (king_to, rook_to) = castling_destinations(from, to);
+            let king = self.remove_piece(king_to);
+            let rook = self.remove_piece(rook_to);
+            self.set_piece(king, from);
+            self.set_piece(rook, to);
+        } else {
+            let piece = match self.remove_piece(to) {
+                _ if mv.promotion().is_some() => Piece::from((PieceType::Pawn, us)),
+                moved => moved,
+            };
+            self.set_piece(piece, from);
+        }
+
+        if mv.is_en_passant() {
+            self.set_piece(state.captured, en_passant_capture_square(to, us));
+        } else if state.captured != Piece::None {
+            self.set_piece(state.captured, to);
+        }
+
+        self.castling_rights = state.castling_rights;
+        self.en_passant = state.en_passant;
+        self.halfmove_clock = state.halfmove_clock;
+        self.hash = state.hash;
+        self.check_info = state.check_info;
+        if us == ChessSide::Black {
+            self.fullmove_number -= 1;
+        }
+    }
+
+    fn from_fen(fen: &str) -> Result<ChessBoard, FenError> {
+        parse_fen(fen)
     }
 
-    fn from_fen(fen: String) -> ChessBoard {
-        // TODO switch this to a proper io reader thing maybe ? I feel like there is a better way
-        // to do this fen stuff
-        let tokens: Vec<&str> = fen.split(' ').collect();
+    fn to_fen(&self) -> String {
+        write_fen(self, false)
+    }
+}
 
-        let mut board = ChessBoard {
+impl ChessBoard {
+    /// The Chess960 starting position with the given number from 0 to 959, using the standard
+    /// numbering in which 518 is the normal chess starting position
+    pub fn from_chess960_index(index: u16) -> Option<ChessBoard> {
+        if index >= 960 {
+            return None;
+        }
+
+        let mut back_rank = [None; 8];
+        let mut n = index as usize;
+        // The bishops go on squares of opposite colours, then the queen and knights fill the
+        // empty squares and the king goes between the rooks on the three that are left
+        back_rank[n % 4 * 2 + 1] = Some('B');
+        n /= 4;
+        back_rank[n % 4 * 2] = Some('B');
+        n /= 4;
+        let mut place = |piece, nth: usize| {
+            let file = (0..8).filter(|&f| back_rank[f].is_none()).nth(nth).unwrap();
+            back_rank[file] = Some(piece);
+        };
+        place('Q', n % 6);
+        n /= 6;
+        let knights = [
+            (0, 1),
+            (0, 2),
+            (0, 3),
+            (0, 4),
+            (1, 2),
+            (1, 3),
+            (1, 4),
+            (2, 3),
+            (2, 4),
+            (3, 4),
+        ];
+        let (first, second) = knights[n];
+        // Placing the first knight shifts the empty squares after it down by one
+        place('N', first);
+        place('N', second - 1);
+        for piece in ['R', 'K', 'R'] {
+            place(piece, 0);
+        }
+
+        let white: String = back_rank.iter().map(|piece| piece.unwrap()).collect();
+        let rooks: String = (0..8)
+            .filter(|&f| back_rank[f] == Some('R'))
+            .rev()
+            .map(|f| (b'A' + f as u8) as char)
+            .collect();
+        let fen = format!(
+            "{}/pppppppp/8/8/8/8/PPPPPPPP/{} w {}{} - 0 1",
+            white.to_ascii_lowercase(),
+            white,
+            rooks,
+            rooks.to_ascii_lowercase()
+        );
+        let mut board = ChessBoard::from_fen(&fen).expect("Chess960 start positions are valid");
+        board.chess960 = true;
+        Some(board)
+    }
+
+    /// Write the position as a Shredder-FEN, which gives castling rights by the file of the rook
+    /// rather than as 'KQkq'
+    pub fn to_shredder_fen(&self) -> String {
+        write_fen(self, true)
+    }
+
+    /// A board with no pieces on it, which the FEN parser fills in
+    pub(super) fn empty() -> ChessBoard {
+        ChessBoard {
             grid: [Piece::None; 64],
             piece_bb: [Bitboard::empty(); PIECE_COUNT],
             side_bb: [Bitboard::empty(); SIDE_COUNT],
             turn: ChessSide::White,
             castling_rights: CastlingRights::default(),
             en_passant: None,
-        };
+            chess960: false,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            hash: 0,
+            check_info: CheckInfo::empty(),
+            psq: PsqState::default(),
+            undo_stack: Vec::new(),
+        }
+    }
 
-        let mut row = 7;
-        for row_str in tokens[0].split('/') {
-            let mut file = 0;
-            for c in row_str.chars() {
-                if file >= 8 {
-                    panic!(
-                        "Invalid fen! Row {} had more than 8 squares as input!\n{}",
-                        row, fen
-                    );
-                }
-                match c {
-                    '1' => file += 0,
-                    '2' => file += 1,
-                    '3' => file += 2,
-                    '4' => file += 3,
-                    '5' => file += 4,
-                    '6' => file += 5,
-                    '7' => file += 6,
-                    '8' => file += 7,
-
-                    'P' => board.place_piece(Piece::WPawn, row, file),
-                    'p' => board.place_piece(Piece::BPawn, row, file),
-                    'N' => board.place_piece(Piece::WKnight, row, file),
-                    'n' => board.place_piece(Piece::BKnight, row, file),
-                    'B' => board.place_piece(Piece::WBishop, row, file),
-                    'b' => board.place_piece(Piece::BBishop, row, file),
-                    'R' => board.place_piece(Piece::WRook, row, file),
-                    'r' => board.place_piece(Piece::BRook, row, file),
-                    'Q' => board.place_piece(Piece::WQueen, row, file),
-                    'q' => board.place_piece(Piece::BQueen, row, file),
-                    'K' => board.place_piece(Piece::WKing, row, file),
-                    'k' => board.place_piece(Piece::BKing, row, file),
-
-                    _ => panic!("Invalid character {}\n{}", c, fen),
-                }
-                file += 1;
-            }
-            if row > 0 {
-                row -= 1;
+    pub(super) fn place_piece(&mut self, piece: Piece, row: u8, file: u8) {
+        self.set_piece(piece, sq(row, file));
+    }
+
+    /// Put a piece on an empty square
+    fn set_piece(&mut self, piece: Piece, sq: u8) {
+        debug_assert!(self.grid[sq as usize] == Piece::None);
+
+        self.grid[sq as usize] = piece;
+        self.piece_bb[piece as usize].set_bit(sq);
+        self.side_bb[ChessSide::from(piece) as usize].set_bit(sq);
+        self.hash ^= KEYS.pieces[piece as usize][sq as usize];
+        self.psq.add(piece, sq);
+    }
+
+    /// Remove the piece on a square, returning the piece that was removed
+    fn remove_piece(&mut self, sq: u8) -> Piece {
+        let piece = self.grid[sq as usize];
+        debug_assert!(piece != Piece::None);
+
+        self.grid[sq as usize] = Piece::None;
+        self.piece_bb[piece as usize].clear_bit(sq);
+        self.side_bb[ChessSide::from(piece) as usize].clear_bit(sq);
+        self.hash ^= KEYS.pieces[piece as usize][sq as usize];
+        self.psq.remove(piece, sq);
+        piece
+    }
+
+    pub fn occ(&self) -> Bitboard {
+        self.side_bb[0] | self.side_bb[1]
+    }
+
+    /// The piece on a square, which is `Piece::None` if the square is empty
+    #[inline]
+    pub fn piece_on(&self, sq: Square) -> Piece {
+        self.grid[sq.index() as usize]
+    }
+
+    /// Return true if the move captures a piece, including en passant
+    #[inline]
+    pub fn is_capture(&self, mv: ChessMove) -> bool {
+        mv.is_en_passant() || self.side_bb[!self.turn].is_set(mv.to().index())
+    }
+
+    /// Return true if the move is castling, which is written as the king capturing its own rook
+    #[inline]
+    pub fn is_castling(&self, mv: ChessMove) -> bool {
+        self.piece_on(mv.from()) == Piece::from((PieceType::King, self.turn))
+            && self.piece_on(mv.to()) == Piece::from((PieceType::Rook, self.turn))
+    }
+
+    /// The Zobrist hash of the position
+    #[inline]
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    /// The en passant part of the hash. The en passant square is only hashed when the side to
+    /// move has a pawn which attacks it, so that positions which only differ by an unusable en
+    /// passant square hash the same.
+    fn en_passant_key(&self) -> u64 {
+        match self.en_passant {
+            Some(sq)
+                if !(get_pawn_attacks(sq.index(), !self.turn)
+                    & self.piece_bb[Piece::from((PieceType::Pawn, self.turn))])
+                .is_empty() =>
+            {
+                KEYS.en_passant_file[sq.file() as usize]
             }
+            _ => 0,
         }
+    }
 
-        match tokens[1] {
-            "w" => board.turn = ChessSide::White,
-            "b" => board.turn = ChessSide::Black,
-            _ => panic!("Invalid side to move {}\n{}", tokens[1], fen),
-        }
-
-        // Castling rights
-        if tokens[2] != "-" {
-            for c in tokens[2].chars() {
-                match c {
-                    'K' => board.castling_rights.set_right(CastlingRight::WhiteKing),
-                    'Q' => board.castling_rights.set_right(CastlingRight::WhiteQueen),
-                    'k' => board.castling_rights.set_right(CastlingRight::BlackKing),
-                    'q' => board.castling_rights.set_right(CastlingRight::BlackQueen),
-                    _ => panic!(
-                        "Invalid castling right {} for the castling rights {}",
-                        c, tokens[2]
-                    ),
+    /// Return true if the current position has occurred at least `count` times, counting this
+    /// one. Only positions since the last capture or pawn move can repeat, so the search stops
+    /// there. Search can use a count of 2, and games are adjudicated with 3 or 5.
+    pub fn is_repetition(&self, count: usize) -> bool {
+        let mut seen = 1;
+        // The position before the last move has the other side to move, so start two back and
+        // step by two
+        let positions = self
+            .undo_stack
+            .iter()
+            .rev()
+            .take(self.halfmove_clock as usize)
+            .skip(1)
+            .step_by(2);
+        for state in positions {
+            if state.hash == self.hash {
+                seen += 1;
+                if seen >= count {
+                    return true;
                 }
             }
         }
+        seen >= count
+    }
 
-        // En passant
-        if tokens[3] != "-" {
-            assert!(
-                tokens[3].len() == 2,
-                "Invalid en passant square {}",
-                tokens[3]
-            );
-            let mut chars = tokens[3].chars();
-            // TODO Probably should make a function to construct a square from a string
-            board.en_passant = Some(Square(
-                match chars.next() {
-                    Some('a') => 0,
-                    Some('b') => 1,
-                    Some('c') => 2,
-                    Some('d') => 3,
-                    Some('e') => 4,
-                    Some('f') => 5,
-                    Some('g') => 6,
-                    Some('h') => 7,
-                    _ => panic!("Invalid file for the en passant square {}", tokens[3]),
-                },
-                match chars.next() {
-                    Some('0') => 0,
-                    Some('1') => 1,
-                    Some('2') => 2,
-                    Some('3') => 3,
-                    Some('4') => 4,
-                    Some('5') => 5,
-                    Some('6') => 6,
-                    Some('7') => 7,
-                    _ => panic!("Invalid rank for the en passant square {}", tokens[3]),
-                },
-            ));
-        }
-
-        // Halfmove clock
-        // Full move number
-
-        board
+    /// Return true if either side may claim a draw by the fifty-move rule
+    pub fn is_fifty_move_draw(&self) -> bool {
+        self.halfmove_clock >= 100
+    }
+
+    /// Return true if the game is drawn automatically by the seventy-five-move rule
+    pub fn is_seventy_five_move_draw(&self) -> bool {
+        self.halfmove_clock >= 150
+    }
+
+    /// Return true if neither side has enough material to checkmate, which is the case when
+    /// there are only kings and at most one minor piece, or only kings and bishops which are all
+    /// on squares of the same colour.
+    pub fn is_insufficient_material(&self) -> bool {
+        let pieces = |pt| {
+            self.piece_bb[Piece::from((pt, ChessSide::White))]
+                | self.piece_bb[Piece::from((pt, ChessSide::Black))]
+        };
+
+        let heavy = pieces(PieceType::Pawn) | pieces(PieceType::Rook) | pieces(PieceType::Queen);
+        if !heavy.is_empty() {
+            return false;
+        }
+
+        let knights = pieces(PieceType::Knight);
+        let bishops = pieces(PieceType::Bishop);
+        let minors = (knights | bishops).popcount();
+
+        const LIGHT_SQUARES: u64 = 0x55AA55AA55AA55AA;
+        minors <= 1
+            || knights.is_empty()
+                && ((bishops & LIGHT_SQUARES).is_empty() || (bishops & !LIGHT_SQUARES).is_empty())
+    }
+
+    /// Recompute the hash, evaluation terms, checks and pins of the position after it has been
+    /// set up
+    pub(super) fn set_state(&mut self) {
+        self.hash = self.compute_hash();
+        self.psq = PsqState::compute(self);
+        self.update_check_info();
+    }
+
+    /// Compute the hash of the position from scratch
+    fn compute_hash(&self) -> u64 {
+        let mut hash = self.en_passant_key() ^ KEYS.castling[self.castling_rights.index()];
+        if self.turn == ChessSide::Black {
+            hash ^= KEYS.side;
+        }
+        for (sq, &piece) in self.grid.iter().enumerate() {
+            if piece != Piece::None {
+                hash ^= KEYS.pieces[piece as usize][sq];
+            }
+        }
+        hash
     }
 }
 
-impl ChessBoard {
-    fn place_piece(&mut self, piece: Piece, row: u8, file: u8) {
-        self.grid[sq(row, file) as usize] = piece;
-        self.piece_bb[piece as usize].set_sq(row, file);
-        self.side_bb[ChessSide::from(piece) as usize].set_sq(row, file);
+/// The square of the pawn captured by an en passant move to the given square
+pub(super) fn en_passant_capture_square(to: u8, us: ChessSide) -> u8 {
+    match us {
+        ChessSide::White => to - 8,
+        ChessSide::Black => to + 8,
     }
+}
 
-    pub fn occ(&self) -> Bitboard {
-        self.side_bb[0] | self.side_bb[1]
+/// The squares the king and rook end up on when castling, which are always the g and f files on
+/// the king side and the c and d files on the queen side, wherever they started
+pub(super) fn castling_destinations(king_from: u8, rook_from: u8) -> (u8, u8) {
+    let rank_start = king_from & !7;
+    if rook_from > king_from {
+        (rank_start + 6, rank_start + 5)
+    } else {
+        (rank_start + 2, rank_start + 3)
     }
 }
 
@@ -222,19 +612,8 @@ fn print_piece(p: Piece) {
     print!(
         "{}",
         match p {
-            Piece::WPawn => "P",
-            Piece::BPawn => "p",
-            Piece::WKnight => "K",
-            Piece::BKnight => "k",
-            Piece::WBishop => "B",
-            Piece::BBishop => "b",
-            Piece::WRook => "R",
-            Piece::BRook => "r",
-            Piece::WQueen => "Q",
-            Piece::BQueen => "q",
-            Piece::WKing => "K",
-            Piece::BKing => "k",
-            _ => ".",
+            Piece::None => '.',
+            _ => p.to_char(),
         }
     )
 }
@@ -259,3 +638,400 @@ impl ChessBoard {
         println!("└─┴─┴─┴─┴─┴─┴─┴─┘");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MoveGen;
+
+    fn board(fen: &str) -> ChessBoard {
+        ChessBoard::from_fen(fen).unwrap()
+    }
+
+    #[test]
+    fn double_push_sets_en_passant() {
+        let mut board = board("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        board.make_move(ChessMove::new(12, 28, 0, 0));
+
+        assert_eq!(board.grid[28], Piece::WPawn);
+        assert_eq!(board.grid[12], Piece::None);
+        assert!(board.piece_bb[Piece::WPawn].is_set(28));
+        assert!(board.side_bb[ChessSide::White].is_set(28));
+        assert!(!board.side_bb[ChessSide::White].is_set(12));
+        assert_eq!(board.en_passant, Some(Square::from_index(20)));
+        assert_eq!(board.turn, ChessSide::Black);
+    }
+
+    #[test]
+    fn capture_removes_piece() {
+        let mut board = board("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1");
+        board.make_move(ChessMove::new(28, 35, 0, 0));
+
+        assert_eq!(board.grid[35], Piece::WPawn);
+        assert!(board.piece_bb[Piece::BPawn].is_empty());
+        assert_eq!(board.side_bb[ChessSide::Black], Bitboard::square(60));
+        assert_eq!(board.en_passant, None);
+    }
+
+    #[test]
+    fn en_passant_capture() {
+        let mut board = board("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1");
+        board.make_move(ChessMove::new(36, 43, 0, 1));
+
+        assert_eq!(board.grid[43], Piece::WPawn);
+        assert_eq!(board.grid[35], Piece::None);
+        assert!(board.piece_bb[Piece::BPawn].is_empty());
+    }
+
+    #[test]
+    fn promotion() {
+        let mut board = board("1r2k3/P7/8/8/8/8/8/4K3 w - - 0 1");
+        board.make_move(ChessMove::new(48, 57, PieceType::Queen as u16, 0));
+
+        assert_eq!(board.grid[57], Piece::WQueen);
+        assert!(board.piece_bb[Piece::WPawn].is_empty());
+        assert!(board.piece_bb[Piece::BRook].is_empty());
+        assert!(board.piece_bb[Piece::WQueen].is_set(57));
+    }
+
+    #[test]
+    fn castling_moves_rook() {
+        let mut board = board("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+        board.make_move(ChessMove::new(4, 7, 0, 0));
+
+        assert_eq!(board.grid[6], Piece::WKing);
+        assert_eq!(board.grid[5], Piece::WRook);
+        assert_eq!(board.grid[7], Piece::None);
+        assert!(!board.castling_rights.has_right(CastlingRight::WhiteKing));
+        assert!(!board.castling_rights.has_right(CastlingRight::WhiteQueen));
+
+        board.make_move(ChessMove::new(60, 56, 0, 0));
+
+        assert_eq!(board.grid[58], Piece::BKing);
+        assert_eq!(board.grid[59], Piece::BRook);
+        assert_eq!(board.grid[56], Piece::None);
+        assert!(!board.castling_rights.has_right(CastlingRight::BlackKing));
+        assert!(!board.castling_rights.has_right(CastlingRight::BlackQueen));
+    }
+
+    #[test]
+    fn rook_capture_removes_castling_right() {
+        let mut board = board("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+        board.make_move(ChessMove::new(0, 56, 0, 0));
+
+        assert!(!board.castling_rights.has_right(CastlingRight::WhiteQueen));
+        assert!(!board.castling_rights.has_right(CastlingRight::BlackQueen));
+        assert!(board.castling_rights.has_right(CastlingRight::WhiteKing));
+        assert!(board.castling_rights.has_right(CastlingRight::BlackKing));
+    }
+
+    #[test]
+    fn chess960_castling() {
+        // The king on f1 castles king side with the rook on g1, so they swap places
+        let mut board = board("4k3/8/8/8/8/8/8/5KR1 w G - 0 1");
+        board.make_move(ChessMove::new(5, 6, 0, 0));
+
+        assert_eq!(board.grid[6], Piece::WKing);
+        assert_eq!(board.grid[5], Piece::WRook);
+        assert!(!board.castling_rights.has_right(CastlingRight::WhiteKing));
+        assert_eq!(board.hash(), board.compute_hash());
+
+        board.unmake_move(ChessMove::new(5, 6, 0, 0));
+        assert_eq!(board.to_shredder_fen(), "4k3/8/8/8/8/8/8/5KR1 w G - 0 1");
+    }
+
+    #[test]
+    fn chess960_start_positions() {
+        assert_eq!(
+            ChessBoard::from_chess960_index(518).unwrap().to_fen(),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+        );
+        assert_eq!(
+            ChessBoard::from_chess960_index(0).unwrap().to_fen(),
+            "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w KQkq - 0 1"
+        );
+        assert_eq!(
+            ChessBoard::from_chess960_index(959).unwrap().to_fen(),
+            "rkrnnqbb/pppppppp/8/8/8/8/PPPPPPPP/RKRNNQBB w KQkq - 0 1"
+        );
+        assert!(ChessBoard::from_chess960_index(960).is_none());
+
+        let mut back_ranks: Vec<_> = (0..960)
+            .map(|i| ChessBoard::from_chess960_index(i).unwrap().grid[..8].to_vec())
+            .collect();
+        back_ranks.sort_by_key(|rank| format!("{:?}", rank));
+        back_ranks.dedup();
+        assert_eq!(back_ranks.len(), 960);
+    }
+
+    #[test]
+    fn move_counters() {
+        let mut board = board("r3k2r/8/8/8/8/8/4P3/R3K2R w KQkq - 7 20");
+
+        board.make_move(ChessMove::new(0, 1, 0, 0));
+        assert_eq!((board.halfmove_clock, board.fullmove_number), (8, 20));
+        board.make_move(ChessMove::new(63, 62, 0, 0));
+        assert_eq!((board.halfmove_clock, board.fullmove_number), (9, 21));
+        board.make_move(ChessMove::new(12, 20, 0, 0));
+        assert_eq!((board.halfmove_clock, board.fullmove_number), (0, 21));
+        board.make_move(ChessMove::new(62, 61, 0, 0));
+        assert_eq!((board.halfmove_clock, board.fullmove_number), (1, 22));
+        board.make_move(ChessMove::new(1, 56, 0, 0));
+        assert_eq!((board.halfmove_clock, board.fullmove_number), (0, 22));
+
+        board.unmake_move(ChessMove::new(1, 56, 0, 0));
+        board.unmake_move(ChessMove::new(62, 61, 0, 0));
+        board.unmake_move(ChessMove::new(12, 20, 0, 0));
+        assert_eq!((board.halfmove_clock, board.fullmove_number), (9, 21));
+        board.unmake_move(ChessMove::new(63, 62, 0, 0));
+        board.unmake_move(ChessMove::new(0, 1, 0, 0));
+        assert_eq!(board.to_fen(), "r3k2r/8/8/8/8/8/4P3/R3K2R w KQkq - 7 20");
+    }
+
+    #[test]
+    fn unmake_restores_position() {
+        let cases = [
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                ChessMove::new(12, 28, 0, 0),
+            ),
+            (
+                "4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1",
+                ChessMove::new(28, 35, 0, 0),
+            ),
+            (
+                "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1",
+                ChessMove::new(36, 43, 0, 1),
+            ),
+            (
+                "1r2k3/P7/8/8/8/8/8/4K3 w - - 0 1",
+                ChessMove::new(48, 57, PieceType::Queen as u16, 0),
+            ),
+            (
+                "4k3/8/8/8/8/8/p7/4K3 b - - 0 1",
+                ChessMove::new(8, 0, PieceType::Knight as u16, 0),
+            ),
+            (
+                "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1",
+                ChessMove::new(4, 7, 0, 0),
+            ),
+            (
+                "r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1",
+                ChessMove::new(60, 56, 0, 0),
+            ),
+            (
+                "r3k3/8/8/8/8/8/8/RK5R w HAa - 0 1",
+                ChessMove::new(1, 0, 0, 0),
+            ),
+            (
+                "rk3r2/8/8/8/8/8/8/4K3 b fa - 0 1",
+                ChessMove::new(57, 61, 0, 0),
+            ),
+            (
+                "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1",
+                ChessMove::new(0, 56, 0, 0),
+            ),
+        ];
+
+        for (fen, mv) in cases {
+            let mut board = board(fen);
+            let original = board.clone();
+
+            board.make_move(mv);
+            assert_ne!(board, original);
+            board.unmake_move(mv);
+            assert_eq!(board, original, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn hash_transpositions() {
+        let start = board("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+
+        // 1. Nf3 Nf6 2. Nc3 and 1. Nc3 Nf6 2. Nf3 reach the same position
+        let mut a = start.clone();
+        for mv in [(6, 21), (62, 45), (1, 18)] {
+            a.make_move(ChessMove::new(mv.0, mv.1, 0, 0));
+        }
+        let mut b = start.clone();
+        for mv in [(1, 18), (62, 45), (6, 21)] {
+            b.make_move(ChessMove::new(mv.0, mv.1, 0, 0));
+        }
+        assert_eq!(a.hash(), b.hash());
+
+        // An en passant square that can't be captured on doesn't change the hash
+        let mut c = start.clone();
+        c.make_move(ChessMove::new(12, 28, 0, 0));
+        let d = board("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1");
+        assert_eq!(c.hash(), d.hash());
+
+        // But one that can does
+        let e = board("rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1");
+        let f = board("rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1");
+        assert_ne!(e.hash(), f.hash());
+
+        assert_ne!(start.hash(), a.hash());
+    }
+
+    #[test]
+    fn incremental_state_matches_random_games() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        ];
+        // xorshift, so that the games are the same every run
+        let mut rng = 0x2545F4914F6CDD1Du64;
+        let mut random = |n: usize| {
+            rng ^= rng << 13;
+            rng ^= rng >> 7;
+            rng ^= rng << 17;
+            (rng % n as u64) as usize
+        };
+
+        for fen in fens {
+            for _ in 0..20 {
+                let mut board = board(fen);
+                let original = board.clone();
+                let mut played = Vec::new();
+
+                for _ in 0..100 {
+                    let moves = board.gen_legal();
+                    if moves.is_empty() {
+                        break;
+                    }
+                    let mv = moves[random(moves.len())];
+                    board.make_move(mv);
+                    played.push(mv);
+                    assert_eq!(board.hash(), board.compute_hash(), "{}", board.to_fen());
+                    assert_eq!(board.psq, PsqState::compute(&board), "{}", board.to_fen());
+                }
+
+                for mv in played.into_iter().rev() {
+                    board.unmake_move(mv);
+                    assert_eq!(board.hash(), board.compute_hash(), "{}", board.to_fen());
+                }
+                assert_eq!(board, original);
+            }
+        }
+    }
+
+    #[test]
+    fn repetition() {
+        let mut board = board("4k3/8/8/8/8/8/8/4K1N1 w - - 0 1");
+        let shuffle = [
+            ChessMove::new(6, 21, 0, 0),
+            ChessMove::new(60, 59, 0, 0),
+            ChessMove::new(21, 6, 0, 0),
+            ChessMove::new(59, 60, 0, 0),
+        ];
+
+        assert!(!board.is_repetition(2));
+        for mv in shuffle {
+            board.make_move(mv);
+        }
+        assert!(board.is_repetition(2));
+        assert!(!board.is_repetition(3));
+
+        // Positions in between don't count as repetitions of the current one
+        board.make_move(shuffle[0]);
+        assert!(board.is_repetition(2));
+        assert!(!board.is_repetition(3));
+        for mv in &shuffle[1..] {
+            board.make_move(*mv);
+        }
+        assert!(board.is_repetition(3));
+
+        for _ in 0..2 {
+            for mv in shuffle {
+                board.make_move(mv);
+            }
+        }
+        assert!(board.is_repetition(5));
+        assert!(!board.is_repetition(6));
+    }
+
+    #[test]
+    fn repetition_stops_at_irreversible_moves() {
+        let mut board = board("4k3/p7/8/8/8/8/8/4K1N1 b - - 0 1");
+        let moves = [
+            ChessMove::new(60, 59, 0, 0),
+            ChessMove::new(6, 21, 0, 0),
+            ChessMove::new(59, 60, 0, 0),
+            ChessMove::new(21, 6, 0, 0),
+        ];
+        for mv in moves {
+            board.make_move(mv);
+        }
+        assert!(board.is_repetition(2));
+
+        // After a pawn move nothing before it can repeat
+        board.make_move(ChessMove::new(48, 40, 0, 0));
+        for mv in [
+            ChessMove::new(6, 21, 0, 0),
+            ChessMove::new(60, 59, 0, 0),
+            ChessMove::new(21, 6, 0, 0),
+        ] {
+            board.make_move(mv);
+            assert!(!board.is_repetition(2));
+        }
+    }
+
+    #[test]
+    fn move_rules() {
+        assert!(!board("4k3/8/8/8/8/8/8/4K1N1 w - - 99 80").is_fifty_move_draw());
+        assert!(board("4k3/8/8/8/8/8/8/4K1N1 w - - 100 80").is_fifty_move_draw());
+        assert!(!board("4k3/8/8/8/8/8/8/4K1N1 w - - 149 80").is_seventy_five_move_draw());
+        assert!(board("4k3/8/8/8/8/8/8/4K1N1 w - - 150 80").is_seventy_five_move_draw());
+    }
+
+    #[test]
+    fn insufficient_material() {
+        let cases = [
+            ("4k3/8/8/8/8/8/8/4K3 w - - 0 1", true),
+            ("4k3/8/8/8/8/8/8/4KN2 w - - 0 1", true),
+            ("4k3/8/8/8/8/8/8/4KB2 w - - 0 1", true),
+            ("4kb2/8/8/8/8/8/8/4K3 w - - 0 1", true),
+            ("4k3/8/8/8/8/8/8/4KNN1 w - - 0 1", false),
+            ("4kn2/8/8/8/8/8/8/4KN2 w - - 0 1", false),
+            ("4kn2/8/8/8/8/8/8/4KB2 w - - 0 1", false),
+            // Bishops on f1, c8 and b3 are all on light squares
+            ("2b1k3/8/8/8/8/1B6/8/4KB2 w - - 0 1", true),
+            ("3bk3/8/8/8/8/8/8/4KB2 w - - 0 1", false),
+            ("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1", false),
+            ("4k3/8/8/8/8/8/8/4KR2 w - - 0 1", false),
+            ("4k3/8/8/8/8/8/8/3QK3 w - - 0 1", false),
+        ];
+        for (fen, insufficient) in cases {
+            assert_eq!(
+                board(fen).is_insufficient_material(),
+                insufficient,
+                "{}",
+                fen
+            );
+        }
+    }
+
+    #[test]
+    fn unmake_sequence() {
+        let mut board = board("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        let original = board.clone();
+        let moves = [
+            ChessMove::new(12, 28, 0, 0),
+            ChessMove::new(51, 35, 0, 0),
+            ChessMove::new(28, 35, 0, 0),
+            ChessMove::new(59, 35, 0, 0),
+            ChessMove::new(6, 21, 0, 0),
+            ChessMove::new(35, 11, 0, 0),
+        ];
+
+        for mv in moves {
+            board.make_move(mv);
+        }
+        for mv in moves.iter().rev() {
+            board.unmake_move(*mv);
+        }
+        assert_eq!(board, original);
+    }
+}
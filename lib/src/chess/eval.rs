@@ -0,0 +1,330 @@
+//! Static evaluation of a position. Each piece scores its material plus a bonus for the square it
+//! stands on, with separate values for the middlegame and the endgame. The two scores are blended
+//! by how much material is left on the board, so that, for example, the king hides in the corner
+//! early on but walks to the centre once the queens are gone.
+
+use super::board::ChessBoard;
+use super::types::*;
+
+/// The value of each piece type in centipawns. The king can never be captured, so it has none.
+/// These are used where a single rough value is needed, such as in move ordering, pruning and
+/// static exchange evaluation, which all share them so that they agree on what a capture wins.
+pub const PIECE_VALUES: [i32; PIECE_TYPE_COUNT] = [100, 325, 325, 500, 1000, 0];
+
+/// The value of each piece type in the middlegame and the endgame
+const MG_VALUES: [i32; PIECE_TYPE_COUNT] = [82, 337, 365, 477, 1025, 0];
+const EG_VALUES: [i32; PIECE_TYPE_COUNT] = [94, 281, 297, 512, 936, 0];
+
+/// How much each piece type counts towards the game phase. With all the pieces on the board the
+/// phase is `MAX_PHASE`, which is pure middlegame, and with only kings and pawns it is zero, which
+/// is pure endgame.
+const PHASE_WEIGHTS: [i32; PIECE_TYPE_COUNT] = [0, 1, 1, 2, 4, 0];
+const MAX_PHASE: i32 = 24;
+
+// The piece-square tables, from white's point of view and laid out the way the board is drawn, so
+// the first row is the eighth rank. These are the PeSTO tables.
+#[rustfmt::skip]
+const MG_TABLES: [[i32; 64]; PIECE_TYPE_COUNT] = [
+    // Pawn
+    [
+          0,   0,   0,   0,   0,   0,   0,   0,
+         98, 134,  61,  95,  68, 126,  34, -11,
+         -6,   7,  26,  31,  65,  56,  25, -20,
+        -14,  13,   6,  21,  23,  12,  17, -23,
+        -27,  -2,  -5,  12,  17,   6,  10, -25,
+        -26,  -4,  -4, -10,   3,   3,  33, -12,
+        -35,  -1, -20, -23, -15,  24,  38, -22,
+          0,   0,   0,   0,   0,   0,   0,   0,
+    ],
+    // Knight
+    [
+       -167, -89, -34, -49,  61, -97, -15,-107,
+        -73, -41,  72,  36,  23,  62,   7, -17,
+        -47,  60,  37,  65,  84, 129,  73,  44,
+         -9,  17,  19,  53,  37,  69,  18,  22,
+        -13,   4,  16,  13,  28,  19,  21,  -8,
+        -23,  -9,  12,  10,  19,  17,  25, -16,
+        -29, -53, -12,  -3,  -1,  18, -14, -19,
+       -105, -21, -58, -33, -17, -28, -19, -23,
+    ],
+    // Bishop
+    [
+        -29,   4, -82, -37, -25, -42,   7,  -8,
+        -26,  16, -18, -13,  30,  59,  18, -47,
+        -16,  37,  43,  40,  35,  50,  37,  -2,
+         -4,   5,  19,  50,  37,  37,   7,  -2,
+         -6,  13,  13,  26,  34,  12,  10,   4,
+          0,  15,  15,  15,  14,  27,  18,  10,
+          4,  15,  16,   0,   7,  21,  33,   1,
+        -33,  -3, -14, -21, -13, -12, -39, -21,
+    ],
+    // Rook
+    [
+         32,  42,  32,  51,  63,   9,  31,  43,
+         27,  32,  58,  62,  80,  67,  26,  44,
+         -5,  19,  26,  36,  17,  45,  61,  16,
+        -24, -11,   7,  26,  24,  35,  -8, -20,
+        -36, -26, -12,  -1,   9,  -7,   6, -23,
+        -45, -25, -16, -17,   3,   0,  -5, -33,
+        -44, -16, -20,  -9,  -1,  11,  -6, -71,
+        -19, -13,   1,  17,  16,   7, -37, -26,
+    ],
+    // Queen
+    [
+        -28,   0,  29,  12,  59,  44,  43,  45,
+        -24, -39,  -5,   1, -16,  57,  28,  54,
+        -13, -17,   7,   8,  29,  56,  47,  57,
+        -27, -27, -16, -16,  -1,  17,  -2,   1,
+         -9, -26,  -9, -10,  -2,  -4,   3,  -3,
+        -14,   2, -11,  -2,  -5,   2,  14,   5,
+        -35,  -8,  11,   2,   8,  15,  -3,   1,
+         -1, -18,  -9,  10, -15, -25, -31, -50,
+    ],
+    // King
+    [
+        -65,  23,  16, -15, -56, -34,   2,  13,
+         29,  -1, -20,  -7,  -8,  -4, -38, -29,
+         -9,  24,   2, -16, -20,   6,  22, -22,
+        -17, -20, -12, -27, -30, -25, -14, -36,
+        -49,  -1, -27, -39, -46, -44, -33, -51,
+        -14, -14, -22, -46, -44, -30, -15, -27,
+          1,   7,  -8, -64, -43, -16,   9,   8,
+        -15,  36,  12, -54,   8, -28,  24,  14,
+    ],
+];
+
+#[rustfmt::skip]
+const EG_TABLES: [[i32; 64]; PIECE_TYPE_COUNT] = [
+    // Pawn
+    [
+          0,   0,   0,   0,   0,   0,   0,   0,
+        178, 173, 158, 134, 147, 132, 165, 187,
+         94, 100,  85,  67,  56,  53,  82,  84,
+         32,  24,  13,   5,  -2,   4,  17,  17,
+         13,   9,  -3,  -7,  -7,  -8,   3,  -1,
+          4,   7,  -6,   1,   0,  -5,  -1,  -8,
+         13,   8,   8,  10,  13,   0,   2,  -7,
+          0,   0,   0,   0,   0,   0,   0,   0,
+    ],
+    // Knight
+    [
+        -58, -38, -13, -28, -31, -27, -63, -99,
+        -25,  -8, -25,  -2,  -9, -25, -24, -52,
+        -24, -20,  10,   9,  -1,  -9, -19, -41,
+        -17,   3,  22,  22,  22,  11,   8, -18,
+        -18,  -6,  16,  25,  16,  17,   4, -18,
+        -23,  -3,  -1,  15,  10,  -3, -20, -22,
+        -42, -20, -10,  -5,  -2, -20, -23, -44,
+        -29, -51, -23, -15, -22, -18, -50, -64,
+    ],
+    // Bishop
+    [
+        -14, -21, -11,  -8,  -7,  -9, -17, -24,
+         -8,  -4,   7, -12,  -3, -13,  -4, -14,
+          2,  -8,   0,  -1,  -2,   6,   0,   4,
+         -3,   9,  12,   9,  14,  10,   3,   2,
+         -6,   3,  13,  19,   7,  10,  -3,  -9,
+        -12,  -3,   8,  10,  13,   3,  -7, -15,
+        -14, -18,  -7,  -1,   4,  -9, -15, -27,
+        -23,  -9, -23,  -5,  -9, -16,  -5, -17,
+    ],
+    // Rook
+    [
+         13,  10,  18,  15,  12,  12,   8,   5,
+         11,  13,  13,  11,  -3,   3,   8,   3,
+          7,   7,   7,   5,   4,  -3,  -5,  -3,
+          4,   3,  13,   1,   2,   1,  -1,   2,
+          3,   5,   8,   4,  -5,  -6,  -8, -11,
+         -4,   0,  -5,  -1,  -7, -12,  -8, -16,
+         -6,  -6,   0,   2,  -9,  -9, -11,  -3,
+         -9,   2,   3,  -1,  -5, -13,   4, -20,
+    ],
+    // Queen
+    [
+         -9,  22,  22,  27,  27,  19,  10,  20,
+        -17,  20,  32,  41,  58,  25,  30,   0,
+        -20,   6,   9,  49,  47,  35,  19,   9,
+          3,  22,  24,  45,  57,  40,  57,  36,
+        -18,  28,  19,  47,  31,  34,  39,  23,
+        -16, -27,  15,   6,   9,  17,  10,   5,
+        -22, -23, -30, -16, -16, -23, -36, -32,
+        -33, -28, -22, -43,  -5, -32, -20, -41,
+    ],
+    // King
+    [
+        -74, -35, -18, -18, -11,  15,   4, -17,
+        -12,  17,  14,  17,  17,  38,  23,  11,
+         10,  17,  23,  15,  20,  45,  44,  13,
+         -8,  22,  24,  27,  26,  33,  26,   3,
+        -18,  -4,  21,  24,  27,  23,   9, -11,
+        -19,  -3,  11,  21,  23,  16,   7,  -9,
+        -27, -11,   4,  13,  14,   4,  -5, -17,
+        -53, -34, -21, -11, -28, -14, -24, -43,
+    ],
+];
+
+/// The number of real pieces, which leaves out `Piece::None`
+const PIECES: usize = 12;
+
+/// Combine the material values and piece-square tables into one score for each piece on each
+/// square, from white's point of view. Black's pieces use the tables mirrored vertically.
+const fn psq_table(
+    values: [i32; PIECE_TYPE_COUNT],
+    tables: [[i32; 64]; PIECE_TYPE_COUNT],
+) -> [[i32; 64]; PIECES] {
+    let mut psq = [[0; 64]; PIECES];
+    let mut piece = 0;
+    while piece < PIECES {
+        // Pieces alternate between white and black
+        let pt = piece / 2;
+        let mut sq = 0;
+        while sq < 64 {
+            // The tables start from the eighth rank, while squares start from the first
+            psq[piece][sq] = if piece % 2 == 0 {
+                values[pt] + tables[pt][sq ^ 56]
+            } else {
+                -(values[pt] + tables[pt][sq])
+            };
+            sq += 1;
+        }
+        piece += 1;
+    }
+    psq
+}
+
+static MG_PSQ: [[i32; 64]; PIECES] = psq_table(MG_VALUES, MG_TABLES);
+static EG_PSQ: [[i32; 64]; PIECES] = psq_table(EG_VALUES, EG_TABLES);
+
+/// The middlegame and endgame scores of the pieces on the board from white's point of view, along
+/// with the game phase. The board keeps this up to date as pieces are put on and taken off, so
+/// evaluating a position doesn't need to look at every piece.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(super) struct PsqState {
+    mg: i32,
+    eg: i32,
+    phase: i32,
+}
+
+impl PsqState {
+    #[inline]
+    pub(super) fn add(&mut self, piece: Piece, sq: u8) {
+        self.mg += MG_PSQ[piece as usize][sq as usize];
+        self.eg += EG_PSQ[piece as usize][sq as usize];
+        self.phase += PHASE_WEIGHTS[piece as usize / 2];
+    }
+
+    #[inline]
+    pub(super) fn remove(&mut self, piece: Piece, sq: u8) {
+        self.mg -= MG_PSQ[piece as usize][sq as usize];
+        self.eg -= EG_PSQ[piece as usize][sq as usize];
+        self.phase -= PHASE_WEIGHTS[piece as usize / 2];
+    }
+
+    /// Compute the scores of a position from scratch
+    pub(super) fn compute(board: &ChessBoard) -> PsqState {
+        let mut state = PsqState::default();
+        for (sq, &piece) in board.grid.iter().enumerate() {
+            if piece != Piece::None {
+                state.add(piece, sq as u8);
+            }
+        }
+        state
+    }
+}
+
+impl ChessBoard {
+    /// The static evaluation of the position in centipawns, from the point of view of the side to
+    /// move
+    pub fn evaluate(&self) -> i32 {
+        let PsqState { mg, eg, phase } = self.psq;
+        // Promotions can take the phase past the starting material
+        let phase = phase.min(MAX_PHASE);
+        let score = (mg * phase + eg * (MAX_PHASE - phase)) / MAX_PHASE;
+
+        match self.turn {
+            ChessSide::White => score,
+            ChessSide::Black => -score,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::*;
+
+    const FENS: [&str; 6] = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    ];
+
+    /// Mirror a position vertically and swap the colours of the pieces, giving the same position
+    /// with the other side to move
+    fn flip_fen(fen: &str) -> String {
+        let swap_case = |s: &str| -> String {
+            s.chars()
+                .map(|c| match c.is_ascii_uppercase() {
+                    true => c.to_ascii_lowercase(),
+                    false => c.to_ascii_uppercase(),
+                })
+                .collect()
+        };
+        let fields: Vec<_> = fen.split(' ').collect();
+        let placement: Vec<_> = fields[0].split('/').rev().collect();
+        let turn = if fields[1] == "w" { "b" } else { "w" };
+        let en_passant = match fields[3].as_bytes() {
+            [file, rank] => format!("{}{}", *file as char, (b'9' - rank + b'0') as char),
+            _ => "-".to_string(),
+        };
+        format!(
+            "{} {} {} {} {} {}",
+            swap_case(&placement.join("/")),
+            turn,
+            swap_case(fields[2]),
+            en_passant,
+            fields[4],
+            fields[5]
+        )
+    }
+
+    #[test]
+    fn colour_flip_symmetry() {
+        for fen in FENS {
+            let board = ChessBoard::from_fen(fen).unwrap();
+            let flipped = ChessBoard::from_fen(&flip_fen(fen)).unwrap();
+            assert_eq!(board.evaluate(), flipped.evaluate(), "{}", fen);
+        }
+
+        // The starting position is its own mirror image
+        let start = ChessBoard::from_fen(FENS[0]).unwrap();
+        assert_eq!(start.evaluate(), 0);
+    }
+
+    #[test]
+    fn material_and_phase() {
+        // An extra rook is worth more than a knight in the endgame, and the side to move sees the
+        // score from its own side
+        let white = ChessBoard::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1").unwrap();
+        let black = ChessBoard::from_fen("4k3/8/8/8/8/8/8/R3K3 b - - 0 1").unwrap();
+        assert!(white.evaluate() > 400);
+        assert_eq!(black.evaluate(), -white.evaluate());
+
+        // Pawns are worth more the closer they get to promoting in the endgame
+        let far = ChessBoard::from_fen("4k3/8/8/8/8/8/P7/4K3 w - - 0 1").unwrap();
+        let near = ChessBoard::from_fen("4k3/P7/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert!(near.evaluate() > far.evaluate() + 100);
+
+        // The king belongs in the corner while there are heavy pieces around, and in the centre
+        // once they are gone
+        let eval = |fen| ChessBoard::from_fen(fen).unwrap().evaluate();
+        assert!(
+            eval("r2qk2r/8/8/8/8/8/8/R2Q2K1 w - - 0 1")
+                > eval("r2qk2r/8/8/8/4K3/8/8/R2Q4 w - - 0 1")
+        );
+        assert!(eval("4k3/8/8/8/8/8/8/6K1 w - - 0 1") < eval("4k3/8/8/8/4K3/8/8/8 w - - 0 1"));
+    }
+}
@@ -0,0 +1,644 @@
+//! Reading and writing positions in Forsyth-Edwards Notation
+
+use super::board::{CastlingRight, ChessBoard, Square, ALL_CASTLING_RIGHTS};
+use super::types::*;
+use std::fmt;
+
+/// The six space separated fields of a FEN
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FenField {
+    PiecePlacement,
+    SideToMove,
+    CastlingRights,
+    EnPassant,
+    HalfmoveClock,
+    FullmoveNumber,
+}
+
+/// The ways a FEN can be malformed. Columns are byte offsets into the FEN string, pointing at
+/// the offending character or the start of the offending field.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FenError {
+    /// The FEN ended before a required field
+    MissingField(FenField),
+    /// There was something after the last field
+    TooManyFields { column: usize },
+    /// A character in the piece placement that is not a piece, a digit from 1 to 8 or a '/'
+    InvalidPiece { column: usize, found: char },
+    /// A rank which does not describe exactly 8 squares. Ranks count from 1 like in algebraic
+    /// notation.
+    InvalidRankLength { column: usize, rank: u8 },
+    /// The piece placement does not describe exactly 8 ranks
+    InvalidRankCount { column: usize },
+    /// A side does not have exactly one king
+    InvalidKingCount { side: ChessSide },
+    /// The side to move was not 'w' or 'b'
+    InvalidSideToMove { column: usize },
+    /// A castling right character that is not one of 'KQkq' or a rook file, or a repeated one
+    InvalidCastlingRights { column: usize, found: char },
+    /// The en passant square is not a square, or not on the rank behind a pawn of the side which
+    /// just moved, with that pawn in front of it and the square and the one behind it empty
+    InvalidEnPassant { column: usize },
+    /// The halfmove clock is not a number
+    InvalidHalfmoveClock { column: usize },
+    /// The fullmove number is not a positive number
+    InvalidFullmoveNumber { column: usize },
+}
+
+impl fmt::Display for FenField {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            FenField::PiecePlacement => "piece placement",
+            FenField::SideToMove => "side to move",
+            FenField::CastlingRights => "castling rights",
+            FenField::EnPassant => "en passant square",
+            FenField::HalfmoveClock => "halfmove clock",
+            FenField::FullmoveNumber => "fullmove number",
+        })
+    }
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FenError::MissingField(field) => write!(f, "missing the {} field", field),
+            FenError::TooManyFields { column } => {
+                write!(f, "unexpected extra field at column {}", column)
+            }
+            FenError::InvalidPiece { column, found } => {
+                write!(f, "invalid piece '{}' at column {}", found, column)
+            }
+            FenError::InvalidRankLength { column, rank } => write!(
+                f,
+                "rank {} ending at column {} does not have 8 squares",
+                rank, column
+            ),
+            FenError::InvalidRankCount { column } => write!(
+                f,
+                "piece placement ending at column {} does not have 8 ranks",
+                column
+            ),
+            FenError::InvalidKingCount { side } => {
+                write!(f, "{:?} does not have exactly one king", side)
+            }
+            FenError::InvalidSideToMove { column } => {
+                write!(f, "invalid side to move at column {}", column)
+            }
+            FenError::InvalidCastlingRights { column, found } => {
+                write!(f, "invalid castling right '{}' at column {}", found, column)
+            }
+            FenError::InvalidEnPassant { column } => {
+                write!(f, "invalid en passant square at column {}", column)
+            }
+            FenError::InvalidHalfmoveClock { column } => {
+                write!(f, "invalid halfmove clock at column {}", column)
+            }
+            FenError::InvalidFullmoveNumber { column } => {
+                write!(f, "invalid fullmove number at column {}", column)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FenError {}
+
+/// Split a FEN into its fields, along with the column each field starts at
+fn fields(fen: &str) -> impl Iterator<Item = (usize, &str)> {
+    fen.split(|c: char| c.is_ascii_whitespace())
+        .scan(0, |column, field| {
+            let start = *column;
+            *column += field.len() + 1;
+            Some((start, field))
+        })
+        .filter(|(_, field)| !field.is_empty())
+}
+
+pub(super) fn parse_fen(fen: &str) -> Result<ChessBoard, FenError> {
+    let mut board = ChessBoard::empty();
+    let mut fields = fields(fen);
+    let mut next_field = |field| fields.next().ok_or(FenError::MissingField(field));
+
+    let (start, placement) = next_field(FenField::PiecePlacement)?;
+    parse_placement(&mut board, start, placement)?;
+
+    let (start, side) = next_field(FenField::SideToMove)?;
+    board.turn = match side {
+        "w" => ChessSide::White,
+        "b" => ChessSide::Black,
+        _ => return Err(FenError::InvalidSideToMove { column: start }),
+    };
+
+    let (start, castling) = next_field(FenField::CastlingRights)?;
+    if castling != "-" {
+        for (i, c) in castling.char_indices() {
+            let invalid = FenError::InvalidCastlingRights {
+                column: start + i,
+                found: c,
+            };
+            let side = if c.is_ascii_uppercase() {
+                ChessSide::White
+            } else {
+                ChessSide::Black
+            };
+            let king_file = back_rank_king(&board, side);
+
+            // 'K' and 'Q' castle with the outermost rook on that side of the king, as in X-FEN,
+            // and a file letter names the rook directly, as in Shredder-FEN. Either way the rook
+            // must be there.
+            let (right, rook_file, shredder) = match c.to_ascii_lowercase() {
+                'k' | 'q' => {
+                    let king_side = c.eq_ignore_ascii_case(&'k');
+                    let Some(rook_file) = outermost_rook(&board, side, king_side) else {
+                        return Err(invalid);
+                    };
+                    (CastlingRight::new(side, king_side), rook_file, false)
+                }
+                file @ 'a'..='h' => {
+                    let rook_file = file as u8 - b'a';
+                    let rook = Piece::from((PieceType::Rook, side));
+                    let rook_square = Square::new(rook_file, back_rank(side));
+                    match king_file {
+                        Some(king_file) if board.grid[rook_square.index() as usize] == rook => (
+                            CastlingRight::new(side, rook_file > king_file),
+                            rook_file,
+                            true,
+                        ),
+                        _ => return Err(invalid),
+                    }
+                }
+                _ => return Err(invalid),
+            };
+            if board.castling_rights.has_right(right) {
+                return Err(invalid);
+            }
+            board.castling_rights.set_right_with_rook(right, rook_file);
+
+            // A king off the e file, or a file letter naming a rook outside the corner, can only
+            // be Chess960. 'KQkq' with a king on the e file is standard chess.
+            let corner = if right.is_king_side() { 7 } else { 0 };
+            if king_file != Some(4) || (shredder && rook_file != corner) {
+                board.chess960 = true;
+            }
+        }
+    }
+
+    let (start, en_passant) = next_field(FenField::EnPassant)?;
+    if en_passant != "-" {
+        // The en passant square is behind a pawn which just double pushed, so that pawn must be
+        // in front of it, and both the square and the one the pawn started from must be empty
+        let (square_rank, pawn_rank, start_rank) = match board.turn {
+            ChessSide::White => (5, 4, 6),
+            ChessSide::Black => (2, 3, 1),
+        };
+        let pawn = Piece::from((PieceType::Pawn, !board.turn));
+        let on = |sq: Square, rank| board.grid[Square::new(sq.file(), rank).index() as usize];
+        board.en_passant = match Square::parse(en_passant) {
+            Some(sq)
+                if sq.rank() == square_rank
+                    && on(sq, pawn_rank) == pawn
+                    && on(sq, square_rank) == Piece::None
+                    && on(sq, start_rank) == Piece::None =>
+            {
+                Some(sq)
+            }
+            _ => return Err(FenError::InvalidEnPassant { column: start }),
+        };
+    }
+
+    // The move counters are often left off, so they are optional
+    if let Some((start, halfmove)) = fields.next() {
+        board.halfmove_clock = halfmove
+            .parse()
+            .map_err(|_| FenError::InvalidHalfmoveClock { column: start })?;
+    }
+    if let Some((start, fullmove)) = fields.next() {
+        board.fullmove_number = match fullmove.parse() {
+            Ok(n) if n > 0 => n,
+            _ => return Err(FenError::InvalidFullmoveNumber { column: start }),
+        };
+    }
+    if let Some((start, _)) = fields.next() {
+        return Err(FenError::TooManyFields { column: start });
+    }
+
+    for side in [ChessSide::White, ChessSide::Black] {
+        let kings = board.piece_bb[Piece::from((PieceType::King, side))];
+        if kings.is_empty() || kings.more_than_one() {
+            return Err(FenError::InvalidKingCount { side });
+        }
+    }
+
+    board.set_state();
+    Ok(board)
+}
+
+/// The file of a side's king if it is on its back rank
+fn back_rank_king(board: &ChessBoard, side: ChessSide) -> Option<u8> {
+    let rank = back_rank(side);
+    let king = Piece::from((PieceType::King, side));
+    (0..8).find(|&file| board.grid[Square::new(file, rank).index() as usize] == king)
+}
+
+/// The file of the rook furthest from the king on one side of it, on a side's back rank
+fn outermost_rook(board: &ChessBoard, side: ChessSide, king_side: bool) -> Option<u8> {
+    let rank = back_rank(side);
+    let king_file = back_rank_king(board, side)?;
+    let rook = Piece::from((PieceType::Rook, side));
+    let is_rook = |&file: &u8| board.grid[Square::new(file, rank).index() as usize] == rook;
+    if king_side {
+        (king_file + 1..8).rev().find(is_rook)
+    } else {
+        (0..king_file).find(is_rook)
+    }
+}
+
+fn back_rank(side: ChessSide) -> u8 {
+    match side {
+        ChessSide::White => 0,
+        ChessSide::Black => 7,
+    }
+}
+
+fn parse_placement(board: &mut ChessBoard, start: usize, placement: &str) -> Result<(), FenError> {
+    let mut rank = 7;
+    let mut file = 0;
+    for (i, c) in placement.char_indices() {
+        let column = start + i;
+        match c {
+            '/' => {
+                if file != 8 {
+                    return Err(FenError::InvalidRankLength {
+                        column,
+                        rank: rank + 1,
+                    });
+                }
+                if rank == 0 {
+                    return Err(FenError::InvalidRankCount { column });
+                }
+                rank -= 1;
+                file = 0;
+            }
+            '1'..='8' => file += c as u8 - b'0',
+            _ => {
+                let piece =
+                    Piece::from_char(c).ok_or(FenError::InvalidPiece { column, found: c })?;
+                if file < 8 {
+                    board.place_piece(piece, rank, file);
+                }
+                file += 1;
+            }
+        }
+        if file > 8 {
+            return Err(FenError::InvalidRankLength {
+                column,
+                rank: rank + 1,
+            });
+        }
+    }
+
+    let end = start + placement.len();
+    if file != 8 {
+        return Err(FenError::InvalidRankLength {
+            column: end,
+            rank: rank + 1,
+        });
+    }
+    if rank != 0 {
+        return Err(FenError::InvalidRankCount { column: end });
+    }
+    Ok(())
+}
+
+/// Write a FEN. Chess960 castling rights are written as in X-FEN, using 'KQkq' for the outermost
+/// rooks and file letters for the others, or always as file letters for Shredder-FEN.
+pub(super) fn write_fen(board: &ChessBoard, shredder: bool) -> String {
+    let mut fen = String::new();
+
+    for rank in (0..8).rev() {
+        let mut empty = 0;
+        for file in 0..8 {
+            match board.grid[Square::new(file, rank).index() as usize] {
+                Piece::None => empty += 1,
+                piece => {
+                    if empty > 0 {
+                        fen.push((b'0' + empty) as char);
+                        empty = 0;
+                    }
+                    fen.push(piece.to_char());
+                }
+            }
+        }
+        if empty > 0 {
+            fen.push((b'0' + empty) as char);
+        }
+        if rank > 0 {
+            fen.push('/');
+        }
+    }
+
+    fen.push(' ');
+    fen.push(match board.turn {
+        ChessSide::White => 'w',
+        ChessSide::Black => 'b',
+    });
+
+    fen.push(' ');
+    let castling_start = fen.len();
+    for right in ALL_CASTLING_RIGHTS {
+        let Some(rook) = board.castling_rights.rook_square(right) else {
+            continue;
+        };
+        let outermost =
+            outermost_rook(board, right.side(), right.is_king_side()) == Some(rook.file());
+        let c = if (board.chess960 && !outermost) || shredder {
+            (b'a' + rook.file()) as char
+        } else if right.is_king_side() {
+            'k'
+        } else {
+            'q'
+        };
+        fen.push(match right.side() {
+            ChessSide::White => c.to_ascii_uppercase(),
+            ChessSide::Black => c,
+        });
+    }
+    if fen.len() == castling_start {
+        fen.push('-');
+    }
+
+    fen.push(' ');
+    match board.en_passant {
+        Some(sq) => fen.push_str(&sq.to_string()),
+        None => fen.push('-'),
+    }
+
+    fen.push_str(&format!(
+        " {} {}",
+        board.halfmove_clock, board.fullmove_number
+    ));
+    fen
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::*;
+
+    #[test]
+    fn round_trip() {
+        let corpus = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+            "rnbqkbnr/pp1ppppp/8/2p5/4P3/8/PPPP1PPP/RNBQKBNR w KQkq c6 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+            "4k3/8/8/8/8/8/8/4K2R w K - 0 1",
+            "r3k3/8/8/8/8/8/8/4K3 b q - 0 1",
+            "7k/8/8/8/8/8/8/K7 w - - 0 1",
+            "8/8/8/3pP3/8/8/8/k6K w - d6 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+            "8/5k2/8/8/8/8/2K5/8 b - - 99 150",
+        ];
+
+        for fen in corpus {
+            let board = ChessBoard::from_fen(fen).unwrap();
+            assert_eq!(board.to_fen(), fen);
+        }
+    }
+
+    #[test]
+    fn optional_counters() {
+        let board = ChessBoard::from_fen("4k3/8/8/8/8/8/8/4K3 w - -").unwrap();
+        assert_eq!(board.to_fen(), "4k3/8/8/8/8/8/8/4K3 w - - 0 1");
+
+        let board = ChessBoard::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 12").unwrap();
+        assert_eq!(board.to_fen(), "4k3/8/8/8/8/8/8/4K3 w - - 12 1");
+    }
+
+    #[test]
+    fn en_passant_square() {
+        let board = ChessBoard::from_fen("8/8/8/3pP3/8/8/8/k6K w - d6 0 1").unwrap();
+        assert_eq!(board.en_passant, Some(Square::new(3, 5)));
+    }
+
+    #[test]
+    fn chess960_castling_rights() {
+        // Shredder-FEN gives the rook files, and X-FEN uses 'KQkq' for the outermost rooks
+        let fen = "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9";
+        let board = ChessBoard::from_fen(fen).unwrap();
+        assert!(board.chess960);
+        assert_eq!(
+            board.castling_rights.rook_square(CastlingRight::WhiteQueen),
+            Some(Square::new(5, 0))
+        );
+        assert_eq!(
+            board.to_fen(),
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KQkq - 2 9"
+        );
+        assert_eq!(board.to_shredder_fen(), fen);
+        assert_eq!(
+            ChessBoard::from_fen(&board.to_fen())
+                .unwrap()
+                .castling_rights,
+            board.castling_rights
+        );
+
+        // A right with a rook which isn't the outermost one needs its file in X-FEN too
+        let board = ChessBoard::from_fen("1r2k3/8/8/8/8/8/8/RR2K3 w Bb - 0 1").unwrap();
+        assert_eq!(
+            board.castling_rights.rook_square(CastlingRight::WhiteQueen),
+            Some(Square::new(1, 0))
+        );
+        assert_eq!(board.to_fen(), "1r2k3/8/8/8/8/8/8/RR2K3 w Bq - 0 1");
+        assert_eq!(
+            board.to_shredder_fen(),
+            "1r2k3/8/8/8/8/8/8/RR2K3 w Bb - 0 1"
+        );
+
+        // Standard positions keep writing 'KQkq', even when read from Shredder-FEN
+        let board = ChessBoard::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        assert!(!board.chess960);
+        assert_eq!(
+            board.to_shredder_fen(),
+            "r3k2r/8/8/8/8/8/8/R3K2R w HAha - 0 1"
+        );
+        let board = ChessBoard::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w HAha - 0 1").unwrap();
+        assert_eq!(board.to_fen(), "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+
+        // 'KQkq' with the king on the e file is standard chess, even with a rook out of its
+        // corner, while a king anywhere else is Chess960
+        let board = ChessBoard::from_fen("4k3/8/8/8/8/8/8/4K1R1 w K - 0 1").unwrap();
+        assert!(!board.chess960);
+        let board = ChessBoard::from_fen("3k3r/8/8/8/8/8/8/3K3R w Kk - 0 1").unwrap();
+        assert!(board.chess960);
+    }
+
+    #[test]
+    fn errors() {
+        let cases = [
+            ("", FenError::MissingField(FenField::PiecePlacement)),
+            (
+                "4k3/8/8/8/8/8/8/4K3",
+                FenError::MissingField(FenField::SideToMove),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w",
+                FenError::MissingField(FenField::CastlingRights),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w -",
+                FenError::MissingField(FenField::EnPassant),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - - 0 1 x",
+                FenError::TooManyFields { column: 30 },
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4X3 w - - 0 1",
+                FenError::InvalidPiece {
+                    column: 17,
+                    found: 'X',
+                },
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K4 w - - 0 1",
+                FenError::InvalidRankLength {
+                    column: 18,
+                    rank: 1,
+                },
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K2 w - - 0 1",
+                FenError::InvalidRankLength {
+                    column: 19,
+                    rank: 1,
+                },
+            ),
+            (
+                "4k3/7/8/8/8/8/8/4K3 w - - 0 1",
+                FenError::InvalidRankLength { column: 5, rank: 7 },
+            ),
+            (
+                "4k3/8/8/8/8/8/4K3 w - - 0 1",
+                FenError::InvalidRankCount { column: 17 },
+            ),
+            (
+                "4k3/8/8/8/8/8/8/8/4K3 w - - 0 1",
+                FenError::InvalidRankCount { column: 17 },
+            ),
+            (
+                "4k3/8/8/8/8/8/8/8 w - - 0 1",
+                FenError::InvalidKingCount {
+                    side: ChessSide::White,
+                },
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 x - - 0 1",
+                FenError::InvalidSideToMove { column: 20 },
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K2R w KX - 0 1",
+                FenError::InvalidCastlingRights {
+                    column: 24,
+                    found: 'X',
+                },
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K2R w KK - 0 1",
+                FenError::InvalidCastlingRights {
+                    column: 24,
+                    found: 'K',
+                },
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w E - 0 1",
+                FenError::InvalidCastlingRights {
+                    column: 22,
+                    found: 'E',
+                },
+            ),
+            (
+                "4k3/8/8/8/8/8/4K3/8 w A - 0 1",
+                FenError::InvalidCastlingRights {
+                    column: 22,
+                    found: 'A',
+                },
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w K - 0 1",
+                FenError::InvalidCastlingRights {
+                    column: 22,
+                    found: 'K',
+                },
+            ),
+            (
+                "r3k3/8/8/8/8/8/8/4K3 w Qk - 0 1",
+                FenError::InvalidCastlingRights {
+                    column: 23,
+                    found: 'Q',
+                },
+            ),
+            (
+                "4k3/8/8/8/8/8/8/R3K3 w Qk - 0 1",
+                FenError::InvalidCastlingRights {
+                    column: 24,
+                    found: 'k',
+                },
+            ),
+            (
+                "4k3/8/8/8/8/8/8/R3K3 w B - 0 1",
+                FenError::InvalidCastlingRights {
+                    column: 23,
+                    found: 'B',
+                },
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - e9 0 1",
+                FenError::InvalidEnPassant { column: 24 },
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - e3 0 1",
+                FenError::InvalidEnPassant { column: 24 },
+            ),
+            (
+                "4k3/8/8/3P4/8/8/8/4K3 w - e6 0 1",
+                FenError::InvalidEnPassant { column: 26 },
+            ),
+            (
+                "4k3/4p3/8/3Pp3/8/8/8/4K3 w - e6 0 1",
+                FenError::InvalidEnPassant { column: 29 },
+            ),
+            (
+                "4k3/8/4n3/3Pp3/8/8/8/4K3 w - e6 0 1",
+                FenError::InvalidEnPassant { column: 29 },
+            ),
+            (
+                "4k3/8/8/8/3pP3/8/8/4K3 b - d3 0 1",
+                FenError::InvalidEnPassant { column: 27 },
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - - x 1",
+                FenError::InvalidHalfmoveClock { column: 26 },
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - - -1 1",
+                FenError::InvalidHalfmoveClock { column: 26 },
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - - 0 0",
+                FenError::InvalidFullmoveNumber { column: 28 },
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - - 0 x",
+                FenError::InvalidFullmoveNumber { column: 28 },
+            ),
+        ];
+
+        for (fen, error) in cases {
+            assert_eq!(ChessBoard::from_fen(fen), Err(error), "{}", fen);
+        }
+    }
+}
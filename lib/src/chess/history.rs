@@ -0,0 +1,220 @@
+//! Statistics about which quiet moves caused cutoffs earlier in the search. Move ordering uses
+//! them to try the quiet moves most likely to cause a cutoff first, since quiet moves have no
+//! captured piece to go by.
+
+use super::board::ChessBoard;
+use super::movegen::ChessMove;
+use super::search::MAX_PLY;
+use super::types::*;
+
+/// Scores in the history tables always stay between plus and minus this
+pub const MAX_HISTORY: i32 = 16384;
+
+/// The number of real pieces, which leaves out `Piece::None`
+const PIECES: usize = 12;
+
+/// A move as the history tables see it: the piece which moved and the square it moved to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PieceTo {
+    pub piece: Piece,
+    pub to: u8,
+}
+
+impl PieceTo {
+    /// The piece and destination of a move which is about to be made on the board
+    pub fn new(board: &ChessBoard, mv: ChessMove) -> Self {
+        PieceTo {
+            piece: board.piece_on(mv.from()),
+            to: mv.to().index(),
+        }
+    }
+}
+
+/// The history tables of a search, along with the killer moves
+pub struct History {
+    // How good each quiet move has been for each side, by [side][from][to]
+    butterfly: Box<[[[i16; 64]; 64]; SIDE_COUNT]>,
+    // The quiet move which last refuted each move, by [piece][to] of the refuted move
+    countermoves: Box<[[Option<ChessMove>; 64]; PIECES]>,
+    // How good each quiet move has been as a reply to the move before it, by [piece][to] of the
+    // move before and [piece][to] of the reply
+    continuation: Vec<i16>,
+    // The last two quiet moves which caused a cutoff at each ply
+    killers: Box<[[Option<ChessMove>; 2]; MAX_PLY]>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl History {
+    pub fn new() -> Self {
+        History {
+            butterfly: Box::new([[[0; 64]; 64]; SIDE_COUNT]),
+            countermoves: Box::new([[None; 64]; PIECES]),
+            continuation: vec![0; PIECES * 64 * PIECES * 64],
+            killers: Box::new([[None; 2]; MAX_PLY]),
+        }
+    }
+
+    /// Forget everything
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Prepare for a new search. The history scores are halved so that what was learned in the
+    /// last search still counts for something but is soon outweighed, and the killers are cleared
+    /// since the plies now refer to different positions.
+    pub fn age(&mut self) {
+        for entry in self.butterfly.iter_mut().flatten().flatten() {
+            *entry /= 2;
+        }
+        for entry in &mut self.continuation {
+            *entry /= 2;
+        }
+        *self.killers = [[None; 2]; MAX_PLY];
+    }
+
+    /// The killer moves at a ply
+    #[inline]
+    pub fn killers(&self, ply: usize) -> [Option<ChessMove>; 2] {
+        self.killers[ply]
+    }
+
+    /// The quiet move which last refuted the previous move
+    #[inline]
+    pub fn countermove(&self, prev: Option<PieceTo>) -> Option<ChessMove> {
+        prev.and_then(|prev| self.countermoves[prev.piece as usize][prev.to as usize])
+    }
+
+    /// The ordering score of a quiet move, given the move played before it
+    #[inline]
+    pub fn quiet_score(&self, board: &ChessBoard, mv: ChessMove, prev: Option<PieceTo>) -> i32 {
+        let (from, to) = (mv.from().index() as usize, mv.to().index() as usize);
+        let mut score = self.butterfly[board.turn as usize][from][to] as i32;
+        if let Some(prev) = prev {
+            score += self.continuation[continuation_index(prev, PieceTo::new(board, mv))] as i32;
+        }
+        score
+    }
+
+    /// Learn from a quiet move causing a cutoff at `depth`. It becomes a killer and the
+    /// countermove to the previous move, and its history scores go up, while those of the quiet
+    /// moves tried before it go down.
+    pub fn update(
+        &mut self,
+        board: &ChessBoard,
+        best: ChessMove,
+        tried: &[ChessMove],
+        prev: Option<PieceTo>,
+        ply: usize,
+        depth: u32,
+    ) {
+        let killers = &mut self.killers[ply];
+        if killers[0] != Some(best) {
+            killers[1] = killers[0];
+            killers[0] = Some(best);
+        }
+        if let Some(prev) = prev {
+            self.countermoves[prev.piece as usize][prev.to as usize] = Some(best);
+        }
+
+        let bonus = history_bonus(depth);
+        self.apply(board, best, prev, bonus);
+        for &mv in tried.iter().filter(|&&mv| mv != best) {
+            self.apply(board, mv, prev, -bonus);
+        }
+    }
+
+    fn apply(&mut self, board: &ChessBoard, mv: ChessMove, prev: Option<PieceTo>, bonus: i32) {
+        let (from, to) = (mv.from().index() as usize, mv.to().index() as usize);
+        gravity(&mut self.butterfly[board.turn as usize][from][to], bonus);
+        if let Some(prev) = prev {
+            let index = continuation_index(prev, PieceTo::new(board, mv));
+            gravity(&mut self.continuation[index], bonus);
+        }
+    }
+}
+
+/// How much a cutoff at a depth changes the history scores. Deeper cutoffs save more work, so
+/// they count for more.
+fn history_bonus(depth: u32) -> i32 {
+    let depth = depth as i32;
+    (16 * depth * depth + 32 * depth).min(1200)
+}
+
+/// Add a bonus to a history score, scaled down as the score gets close to the limit in the
+/// direction of the bonus. This keeps the score within the limit, and lets recent results
+/// outweigh old ones.
+fn gravity(entry: &mut i16, bonus: i32) {
+    let value = *entry as i32;
+    let bonus = bonus.clamp(-MAX_HISTORY, MAX_HISTORY);
+    *entry = (value + bonus - value * bonus.abs() / MAX_HISTORY) as i16;
+}
+
+#[inline]
+fn continuation_index(prev: PieceTo, current: PieceTo) -> usize {
+    ((prev.piece as usize * 64 + prev.to as usize) * PIECES + current.piece as usize) * 64
+        + current.to as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::*;
+
+    #[test]
+    fn gravity_stays_in_bounds() {
+        let mut entry = 0;
+        for _ in 0..1000 {
+            gravity(&mut entry, 1200);
+        }
+        assert!(entry as i32 <= MAX_HISTORY);
+        assert!(entry as i32 > MAX_HISTORY * 9 / 10);
+
+        // A malus pulls a high score back down faster than it went up
+        let high = entry;
+        gravity(&mut entry, -1200);
+        assert!(high - entry > 1200);
+        for _ in 0..1000 {
+            gravity(&mut entry, -1200);
+        }
+        assert!(entry as i32 >= -MAX_HISTORY);
+    }
+
+    #[test]
+    fn cutoffs_update_tables() {
+        let board =
+            ChessBoard::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1")
+                .unwrap();
+        let prev = Some(PieceTo {
+            piece: Piece::WPawn,
+            to: 28,
+        });
+        let mv = |uci| board.parse_uci_move(uci).unwrap();
+        let (best, other) = (mv("e7e5"), mv("a7a6"));
+
+        let mut history = History::new();
+        history.update(&board, best, &[other, best], prev, 3, 4);
+
+        assert_eq!(history.killers(3), [Some(best), None]);
+        assert_eq!(history.killers(4), [None, None]);
+        assert_eq!(history.countermove(prev), Some(best));
+        assert!(history.quiet_score(&board, best, prev) > history.quiet_score(&board, best, None));
+        assert!(history.quiet_score(&board, best, None) > 0);
+        assert!(history.quiet_score(&board, other, prev) < 0);
+
+        // A second cutoff pushes the first killer into the second slot
+        history.update(&board, other, &[other], prev, 3, 4);
+        assert_eq!(history.killers(3), [Some(other), Some(best)]);
+
+        // Aging halves the scores and forgets the killers
+        let score = history.quiet_score(&board, best, None);
+        history.age();
+        assert_eq!(history.quiet_score(&board, best, None), score / 2);
+        assert_eq!(history.killers(3), [None, None]);
+        assert_eq!(history.countermove(prev), Some(other));
+    }
+}
@@ -1,6 +1,32 @@
-/// The implementation for the standard variant of chess
+//! The implementation for the standard variant of chess
 
 mod attacks;
 mod board;
+mod eval;
+mod fen;
+mod history;
 mod movegen;
+mod movepick;
+mod notation;
+mod perft;
+mod search;
+mod see;
+mod status;
+mod tt;
 mod types;
+mod zobrist;
+
+pub use attacks::{set_slider_backend, slider_backend, SliderBackend};
+pub use board::{CastlingRight, CastlingRights, ChessBoard, Square};
+pub use eval::PIECE_VALUES;
+pub use fen::{FenError, FenField};
+pub use history::{History, PieceTo, MAX_HISTORY};
+pub use movegen::{ChessMove, GenType};
+pub use movepick::MovePicker;
+pub use notation::MoveParseError;
+pub use perft::{divide, perft};
+pub use search::{Score, SearchInfo, SearchLimits, Searcher, MATE, MAX_PLY};
+pub use see::SEE_VALUES;
+pub use status::{DrawReason, GameResult, GameStatus};
+pub use tt::{Bound, TranspositionTable, TtEntry, DEFAULT_HASH_MB};
+pub use types::*;
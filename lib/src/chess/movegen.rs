@@ -1,10 +1,13 @@
 use super::attacks::*;
-use super::{board::ChessBoard, types::*};
-use crate::bitboards::Bitboard;
+use super::board::{castling_destinations, en_passant_capture_square, CheckInfo, ChessBoard};
+use super::types::*;
+use crate::bitboards::{between, line, Bitboard, Direction};
 use crate::chess::board::{CastlingRight, Square};
+use crate::movelist::MoveList;
 use crate::types::*;
+use std::fmt;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
 pub struct ChessMove(u16);
 
 impl ChessMove {
@@ -14,55 +17,509 @@ impl ChessMove {
     pub fn new(from: u16, to: u16, promotion: u16, en_pas: u16) -> Self {
         ChessMove(from | to << 6 | promotion << 12 | en_pas << 15)
     }
+
+    /// The square the piece moves from
+    #[inline]
+    pub fn from(&self) -> Square {
+        Square::from_index((self.0 & 0x3F) as u8)
+    }
+
+    /// The square the piece moves to
+    #[inline]
+    pub fn to(&self) -> Square {
+        Square::from_index((self.0 >> 6 & 0x3F) as u8)
+    }
+
+    /// The piece a pawn promotes to, if the move is a promotion
+    #[inline]
+    pub fn promotion(&self) -> Option<PieceType> {
+        match self.0 >> 12 & 0b111 {
+            0 => None,
+            promotion => Some(PieceType::from(promotion as u8)),
+        }
+    }
+
+    /// Return true if the move is an en passant capture
+    #[inline]
+    pub fn is_en_passant(&self) -> bool {
+        self.0 >> 15 != 0
+    }
+
+    /// The move packed into 16 bits, for storing it compactly such as in a hash table
+    #[inline]
+    pub fn to_bits(self) -> u16 {
+        self.0
+    }
+
+    /// Unpack a move from the bits returned by `to_bits`
+    #[inline]
+    pub fn from_bits(bits: u16) -> Self {
+        ChessMove(bits)
+    }
+}
+
+/// Moves are displayed in the long algebraic notation used by UCI, such as "e2e4" or "e7e8q".
+/// Castling is displayed as the king capturing its own rook, as in Chess960, since a move alone
+/// can't tell whether the game is Chess960. Use `ChessBoard::to_uci` for standard castling.
+impl fmt::Display for ChessMove {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.from(), self.to())?;
+        match self.promotion() {
+            Some(PieceType::Knight) => f.write_str("n"),
+            Some(PieceType::Bishop) => f.write_str("b"),
+            Some(PieceType::Rook) => f.write_str("r"),
+            Some(PieceType::Queen) => f.write_str("q"),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl fmt::Debug for ChessMove {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)?;
+        if self.is_en_passant() {
+            f.write_str(" (en passant)")?;
+        }
+        Ok(())
+    }
 }
 
+/// Which legal moves to generate. `Noisy` and `Quiet` split the moves into two halves with
+/// nothing in common: captures, en passant and queen promotions are noisy, while every other move,
+/// including underpromotions and castling, is quiet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum GenType {
     Quiet,
     Noisy,
+    All,
+    /// Every move out of check, which may only be used when the side to move is in check
+    Evasions,
+    /// The quiet moves which give check
+    QuietChecks,
 }
-impl MoveGen for ChessBoard {
-    type Move = ChessMove;
 
-    fn gen_quiet(&self) -> Vec<ChessMove> {
-        let mut mv_list = Vec::new();
+impl GenType {
+    #[inline]
+    fn includes_noisy(self) -> bool {
+        matches!(self, GenType::Noisy | GenType::All | GenType::Evasions)
+    }
 
-        self.gen_pawn_moves(&mut mv_list, GenType::Quiet);
-        self.gen_knight_moves(&mut mv_list, GenType::Quiet);
-        self.gen_king_moves(&mut mv_list, GenType::Quiet);
-        self.gen_slider_moves(&mut mv_list, GenType::Quiet);
-        self.gen_castle_moves(&mut mv_list, GenType::Quiet);
+    #[inline]
+    fn includes_quiet(self) -> bool {
+        self != GenType::Noisy
+    }
+}
 
-        mv_list
+/// The information about checks and pins that is needed to only generate legal moves
+pub struct Legality {
+    king_sq: u8,
+    // Enemy pieces giving check to our king
+    checkers: Bitboard,
+    // Squares a piece other than the king must move to, which are the squares that capture or
+    // block a single checker. This is every square when we are not in check.
+    check_mask: Bitboard,
+    // Our pieces which are pinned to our king
+    pinned: Bitboard,
+    // Squares attacked by the enemy, computed with our king removed so that it can't hide
+    // behind itself from a slider
+    enemy_attacks: Bitboard,
+}
+
+impl Legality {
+    /// Return true if a non-king piece may move from one square to another without leaving the
+    /// king in check
+    #[inline]
+    fn allows(&self, from: u8, to: u8) -> bool {
+        self.check_mask.is_set(to)
+            && (!self.pinned.is_set(from) || line(self.king_sq, from).is_set(to))
     }
+}
+
+impl MoveGen for ChessBoard {
+    type Move = ChessMove;
 
-    fn gen_noisy(&self) -> Vec<ChessMove> {
-        let mut mv_list = Vec::new();
+    fn gen_quiet(&self) -> MoveList<ChessMove> {
+        self.gen_moves(GenType::Quiet)
+    }
 
-        self.gen_pawn_moves(&mut mv_list, GenType::Noisy);
-        self.gen_knight_moves(&mut mv_list, GenType::Noisy);
-        self.gen_king_moves(&mut mv_list, GenType::Noisy);
-        self.gen_slider_moves(&mut mv_list, GenType::Noisy);
-        self.gen_castle_moves(&mut mv_list, GenType::Noisy);
+    fn gen_noisy(&self) -> MoveList<ChessMove> {
+        self.gen_moves(GenType::Noisy)
+    }
 
-        mv_list
+    fn gen_legal(&self) -> MoveList<ChessMove> {
+        self.gen_moves(GenType::All)
     }
 }
 
-fn make_pawn_move(from: u16, to: u16, mv_list: &mut Vec<ChessMove>) {
+/// Add a pawn move of the given type. A promotion to a queen is always noisy and an
+/// underpromotion always quiet, whether or not it captures; otherwise `capture` decides.
+fn make_pawn_move(
+    from: u16,
+    to: u16,
+    capture: bool,
+    gen_type: GenType,
+    mv_list: &mut MoveList<ChessMove>,
+) {
     let to_rank = Square::from_index(to as u8).rank();
     if to_rank == 0 || to_rank == 7 {
-        mv_list.push(ChessMove::new(from, to, 1, 0));
-        mv_list.push(ChessMove::new(from, to, 2, 0));
-        mv_list.push(ChessMove::new(from, to, 3, 0));
-        mv_list.push(ChessMove::new(from, to, 4, 0));
-    } else {
+        if gen_type.includes_quiet() {
+            mv_list.push(ChessMove::new(from, to, 1, 0));
+            mv_list.push(ChessMove::new(from, to, 2, 0));
+            mv_list.push(ChessMove::new(from, to, 3, 0));
+        }
+        if gen_type.includes_noisy() {
+            mv_list.push(ChessMove::new(from, to, 4, 0));
+        }
+    } else if (capture && gen_type.includes_noisy()) || (!capture && gen_type.includes_quiet()) {
         mv_list.push(ChessMove::new(from, to, 0, 0));
     }
 }
 
+/// The squares attacked by a set of pawns belonging to a side
+#[inline]
+pub(super) fn pawn_attacks(pawns: Bitboard, side: ChessSide) -> Bitboard {
+    match side {
+        ChessSide::White => pawns.north_west() | pawns.north_east(),
+        ChessSide::Black => pawns.south_west() | pawns.south_east(),
+    }
+}
+
 impl ChessBoard {
+    /// Generate the legal moves of the given type
+    pub fn gen_moves(&self, gen_type: GenType) -> MoveList<ChessMove> {
+        let mut mv_list = MoveList::new();
+        let legality = self.legality();
+        debug_assert!(
+            gen_type != GenType::Evasions || !legality.checkers.is_empty(),
+            "generating evasions when not in check"
+        );
+
+        self.gen_king_moves(&mut mv_list, gen_type, &legality);
+
+        // Only the king can move out of a double check
+        if !legality.checkers.more_than_one() {
+            self.gen_pawn_moves(&mut mv_list, gen_type, &legality);
+            self.gen_knight_moves(&mut mv_list, gen_type, &legality);
+            self.gen_slider_moves(&mut mv_list, gen_type, &legality);
+            self.gen_castle_moves(&mut mv_list, gen_type, &legality);
+        }
+
+        if gen_type == GenType::QuietChecks {
+            mv_list.retain(|&mv| self.gives_check(mv));
+        }
+        mv_list
+    }
+
+    /// Return true if the move is noisy, meaning it is generated by `GenType::Noisy`
+    #[inline]
+    pub fn is_noisy(&self, mv: ChessMove) -> bool {
+        match mv.promotion() {
+            Some(promotion) => promotion == PieceType::Queen,
+            None => self.is_capture(mv),
+        }
+    }
+
+    /// Return true if a legal move puts the enemy king in check, either directly or by moving
+    /// out of the way of one of our sliders
+    pub fn gives_check(&self, mv: ChessMove) -> bool {
+        let (from, to) = (mv.from().index(), mv.to().index());
+        let king_sq = self.piece_bb[Piece::from((PieceType::King, !self.turn))].lsb() as u8;
+
+        // Most moves can be decided from the cached check squares and blockers. Promotions, en
+        // passant and castling change the board in other ways, so the position after them is
+        // worked out below.
+        if mv.promotion().is_none() && !mv.is_en_passant() && !self.is_castling(mv) {
+            let piece_type = PieceType::from(self.piece_on(mv.from()));
+            return self.check_squares(piece_type).is_set(to)
+                || (self.blockers_for_king(!self.turn).is_set(from)
+                    && !line(king_sq, from).is_set(to));
+        }
+        let ours = |pt| self.piece_bb[Piece::from((pt, self.turn))];
+
+        let mut occ = self.occ();
+        occ.clear_bit(from);
+        occ.set_bit(to);
+        if mv.is_en_passant() {
+            occ.clear_bit(en_passant_capture_square(to, self.turn));
+        }
+
+        // Our sliders as they are after the move
+        let mut bishops =
+            (ours(PieceType::Bishop) | ours(PieceType::Queen)) & !Bitboard::square(from);
+        let mut rooks = (ours(PieceType::Rook) | ours(PieceType::Queen)) & !Bitboard::square(from);
+        let moved = mv
+            .promotion()
+            .unwrap_or_else(|| PieceType::from(self.piece_on(mv.from())));
+        match moved {
+            PieceType::Pawn => {
+                if get_pawn_attacks(to, self.turn).is_set(king_sq) {
+                    return true;
+                }
+            }
+            PieceType::Knight => {
+                if get_knight_attacks(to).is_set(king_sq) {
+                    return true;
+                }
+            }
+            PieceType::Bishop => bishops.set_bit(to),
+            PieceType::Rook => rooks.set_bit(to),
+            PieceType::Queen => {
+                bishops.set_bit(to);
+                rooks.set_bit(to);
+            }
+            PieceType::King => {
+                // The rook is the piece which can give check after castling
+                if self.is_castling(mv) {
+                    let (king_to, rook_to) = castling_destinations(from, to);
+                    occ.clear_bit(to);
+                    occ.set_bit(king_to);
+                    occ.set_bit(rook_to);
+                    rooks.clear_bit(to);
+                    rooks.set_bit(rook_to);
+                }
+            }
+        }
+
+        !(get_bishop_attacks(king_sq, occ) & bishops).is_empty()
+            || !(get_rook_attacks(king_sq, occ) & rooks).is_empty()
+    }
+
+    /// Return true if a move is legal in the position. This is meant for moves that come from
+    /// somewhere other than the move generator, such as a hash move or a killer move, and only
+    /// generates the moves of the type of piece being moved.
+    pub fn is_legal(&self, mv: ChessMove) -> bool {
+        let piece = self.piece_on(mv.from());
+        if piece == Piece::None || ChessSide::from(piece) != self.turn {
+            return false;
+        }
+
+        let legality = self.legality();
+        let piece_type = PieceType::from(piece);
+        if legality.checkers.more_than_one() && piece_type != PieceType::King {
+            return false;
+        }
+
+        let mut mv_list = MoveList::new();
+        match piece_type {
+            PieceType::Pawn => self.gen_pawn_moves(&mut mv_list, GenType::All, &legality),
+            PieceType::Knight => self.gen_knight_moves(&mut mv_list, GenType::All, &legality),
+            PieceType::King => {
+                self.gen_king_moves(&mut mv_list, GenType::All, &legality);
+                self.gen_castle_moves(&mut mv_list, GenType::All, &legality);
+            }
+            _ => self.gen_slider_moves(&mut mv_list, GenType::All, &legality),
+        }
+        mv_list.contains(&mv)
+    }
+
+    /// All pieces of either side which attack a square, with sliders blocked by the given
+    /// occupancy. Passing an occupancy other than the board's shows what would attack the square
+    /// once pieces have moved, such as the sliders behind a capturing piece.
+    pub fn attackers_to(&self, sq: Square, occ: Bitboard) -> Bitboard {
+        let sq = sq.index();
+        let pieces = |pt, side| self.piece_bb[Piece::from((pt, side))];
+        let bishops = pieces(PieceType::Bishop, ChessSide::White)
+            | pieces(PieceType::Bishop, ChessSide::Black)
+            | pieces(PieceType::Queen, ChessSide::White)
+            | pieces(PieceType::Queen, ChessSide::Black);
+        let rooks = pieces(PieceType::Rook, ChessSide::White)
+            | pieces(PieceType::Rook, ChessSide::Black)
+            | pieces(PieceType::Queen, ChessSide::White)
+            | pieces(PieceType::Queen, ChessSide::Black);
+
+        // A pawn attacks a square exactly when a pawn of the other side on that square would
+        // attack the pawn
+        (get_pawn_attacks(sq, ChessSide::Black) & pieces(PieceType::Pawn, ChessSide::White))
+            | (get_pawn_attacks(sq, ChessSide::White) & pieces(PieceType::Pawn, ChessSide::Black))
+            | (get_knight_attacks(sq)
+                & (pieces(PieceType::Knight, ChessSide::White)
+                    | pieces(PieceType::Knight, ChessSide::Black)))
+            | (get_king_attacks(sq)
+                & (pieces(PieceType::King, ChessSide::White)
+                    | pieces(PieceType::King, ChessSide::Black)))
+            | (get_bishop_attacks(sq, occ) & bishops)
+            | (get_rook_attacks(sq, occ) & rooks)
+    }
+
+    /// Return true if any piece of the given side attacks a square
+    pub fn is_square_attacked(&self, sq: Square, by: ChessSide) -> bool {
+        !(self.attackers_to(sq, self.occ()) & self.side_bb[by]).is_empty()
+    }
+
+    /// Every square attacked by a side, with sliders blocked by the given occupancy
+    fn attacked_squares(&self, side: ChessSide, occ: Bitboard) -> Bitboard {
+        let pieces = |pt| self.piece_bb[Piece::from((pt, side))];
+
+        let mut attacks = pawn_attacks(pieces(PieceType::Pawn), side);
+        for from in pieces(PieceType::Knight).lsb_iter() {
+            attacks |= get_knight_attacks(from as u8);
+        }
+        for from in pieces(PieceType::King).lsb_iter() {
+            attacks |= get_king_attacks(from as u8);
+        }
+        for from in (pieces(PieceType::Bishop) | pieces(PieceType::Queen)).lsb_iter() {
+            attacks |= get_bishop_attacks(from as u8, occ);
+        }
+        for from in (pieces(PieceType::Rook) | pieces(PieceType::Queen)).lsb_iter() {
+            attacks |= get_rook_attacks(from as u8, occ);
+        }
+        attacks
+    }
+
+    /// The enemy pieces giving check to the king of the side to move
+    #[inline]
+    pub fn checkers(&self) -> Bitboard {
+        self.check_info.checkers
+    }
+
+    /// Return true if the side to move is in check
+    #[inline]
+    pub fn in_check(&self) -> bool {
+        !self.checkers().is_empty()
+    }
+
+    /// The pieces of either side which are the only piece between the king of the given side and
+    /// an enemy slider. Moving one of our own blockers off the line gives a discovered check.
+    #[inline]
+    pub fn blockers_for_king(&self, side: ChessSide) -> Bitboard {
+        self.check_info.blockers_for_king[side]
+    }
+
+    /// The pieces of a side which are pinned to its own king
+    #[inline]
+    pub fn pinned(&self, side: ChessSide) -> Bitboard {
+        self.blockers_for_king(side) & self.side_bb[side]
+    }
+
+    /// The sliders of a side which pin an enemy piece to the enemy king
+    #[inline]
+    pub fn pinners(&self, side: ChessSide) -> Bitboard {
+        self.check_info.pinners[side]
+    }
+
+    /// The squares from which a piece of the given type belonging to the side to move would give
+    /// check. The king can never give check, so its squares are always empty.
+    #[inline]
+    pub fn check_squares(&self, piece_type: PieceType) -> Bitboard {
+        self.check_info.check_squares[piece_type as usize]
+    }
+
+    /// Recompute the checks and pins after the position has changed
+    pub(super) fn update_check_info(&mut self) {
+        let king_sq = |side| self.piece_bb[Piece::from((PieceType::King, side))].lsb() as u8;
+        let (our_king, their_king) = (king_sq(self.turn), king_sq(!self.turn));
+        let occ = self.occ();
+
+        let (white_blockers, black_pinners) = self.slider_blockers(ChessSide::White);
+        let (black_blockers, white_pinners) = self.slider_blockers(ChessSide::Black);
+        let bishop_checks = get_bishop_attacks(their_king, occ);
+        let rook_checks = get_rook_attacks(their_king, occ);
+
+        self.check_info = CheckInfo {
+            checkers: self.attackers_to(Square::from_index(our_king), occ)
+                & self.side_bb[!self.turn],
+            blockers_for_king: [white_blockers, black_blockers],
+            pinners: [white_pinners, black_pinners],
+            check_squares: [
+                get_pawn_attacks(their_king, !self.turn),
+                get_knight_attacks(their_king),
+                bishop_checks,
+                rook_checks,
+                bishop_checks | rook_checks,
+                Bitboard::empty(),
+            ],
+        };
+    }
+
+    /// The pieces of either side which are the only piece between a side's king and an enemy
+    /// slider, along with the enemy sliders which pin one of that side's pieces
+    fn slider_blockers(&self, side: ChessSide) -> (Bitboard, Bitboard) {
+        let king_sq = self.piece_bb[Piece::from((PieceType::King, side))].lsb() as u8;
+        let occ = self.occ();
+
+        // Enemy sliders which would attack the king on an empty board
+        let enemy = |pt| self.piece_bb[Piece::from((pt, !side))];
+        let snipers = (get_bishop_attacks(king_sq, Bitboard::empty())
+            & (enemy(PieceType::Bishop) | enemy(PieceType::Queen)))
+            | (get_rook_attacks(king_sq, Bitboard::empty())
+                & (enemy(PieceType::Rook) | enemy(PieceType::Queen)));
+
+        let mut blockers = Bitboard::empty();
+        let mut pinners = Bitboard::empty();
+        for sniper in snipers.lsb_iter() {
+            let between = between(king_sq, sniper as u8) & occ;
+            if !between.is_empty() && !between.more_than_one() {
+                blockers |= between;
+                if !(between & self.side_bb[side]).is_empty() {
+                    pinners.set_bit(sniper as u8);
+                }
+            }
+        }
+        (blockers, pinners)
+    }
+
+    /// Compute the checks and pins against the king of the side to move
+    pub fn legality(&self) -> Legality {
+        let occ = self.occ();
+        let king_bb = self.piece_bb[Piece::from((PieceType::King, self.turn))];
+        let king_sq = king_bb.lsb() as u8;
+
+        let checkers = self.checkers();
+        let check_mask = if checkers.is_empty() {
+            !Bitboard::empty()
+        } else {
+            let checker = checkers.lsb() as u8;
+            between(king_sq, checker) | Bitboard::square(checker)
+        };
+
+        Legality {
+            king_sq,
+            checkers,
+            check_mask,
+            pinned: self.pinned(self.turn),
+            enemy_attacks: self.attacked_squares(!self.turn, occ ^ king_bb),
+        }
+    }
+
+    /// Return true if an en passant capture does not leave our king in check. The captured pawn
+    /// and the capturing pawn both leave their squares, which can expose the king along a rank,
+    /// so the resulting position is checked directly.
+    fn en_passant_is_legal(&self, from: u8, to: u8, legality: &Legality) -> bool {
+        let captured = match self.turn {
+            ChessSide::White => to - 8,
+            ChessSide::Black => to + 8,
+        };
+        debug_assert!(
+            self.grid[captured as usize] == Piece::from((PieceType::Pawn, !self.turn)),
+            "no enemy pawn to capture en passant"
+        );
+        let occ = (self.occ() & !Bitboard::square(from) & !Bitboard::square(captured))
+            | Bitboard::square(to);
+        let them = self.side_bb[!self.turn] & !Bitboard::square(captured);
+
+        (self.attackers_to(Square::from_index(legality.king_sq), occ) & them).is_empty()
+    }
+
+    /// The squares a piece other than a pawn may move to for a type of generation
     #[inline]
-    pub fn gen_pawn_moves(&self, mv_list: &mut Vec<ChessMove>, _gen_type: GenType) {
+    fn target_squares(&self, gen_type: GenType) -> Bitboard {
+        let mut targets = Bitboard::empty();
+        if gen_type.includes_noisy() {
+            targets |= self.side_bb[!self.turn];
+        }
+        if gen_type.includes_quiet() {
+            targets |= !self.occ();
+        }
+        targets
+    }
+
+    #[inline]
+    pub fn gen_pawn_moves(
+        &self,
+        mv_list: &mut MoveList<ChessMove>,
+        gen_type: GenType,
+        legality: &Legality,
+    ) {
         // There are a bunch types of pawn moves
         // Singular pushes
         // Double pushes
@@ -73,73 +530,82 @@ impl ChessBoard {
         let our_pawns = self.piece_bb[Piece::from((PieceType::Pawn, self.turn))];
 
         // En passant first, since every other type of move can have promotion
-        if let Some(enpas_sq) = &self.en_passant {
+        if let Some(enpas_sq) = self.en_passant.filter(|_| gen_type.includes_noisy()) {
             // The en passant square is the square that a pawn would move to when doing an en
             // passant.
 
-            // This code is quite ugly! These comments are also quite ugly!
-
             let enpas_sq = enpas_sq.index();
 
-            // These shifts represent the bitwise shift from the en passant square to a square
-            // where a pawn that could do en passant would be placed.
-            let shifts = match self.turn {
-                ChessSide::White => [-7, -9],
-                ChessSide::Black => [7, 9],
+            // These are the directions from the en passant square to the squares a pawn that
+            // could capture en passant would be on
+            let dirs = match self.turn {
+                ChessSide::White => [Direction::SouthEast, Direction::SouthWest],
+                ChessSide::Black => [Direction::NorthWest, Direction::NorthEast],
             };
-            for shift in shifts {
-                let enpas_sq_bb = Bitboard::square(enpas_sq);
-                let pawn_sq_bb = enpas_sq_bb.shift1(shift);
-                if !(pawn_sq_bb & our_pawns).is_empty() {
-                    // :((((( FIX THIS!!! as casts are ugly!!
-                    mv_list.push(ChessMove::new(
-                        (enpas_sq as i8 + shift) as u16,
-                        enpas_sq as u16,
-                        0,
-                        1,
-                    ));
+            for dir in dirs {
+                let pawn_sq_bb = Bitboard::square(enpas_sq).shift(dir) & our_pawns;
+                if pawn_sq_bb.is_empty() {
+                    continue;
+                }
+                let from = pawn_sq_bb.lsb() as u8;
+                if self.en_passant_is_legal(from, enpas_sq, legality) {
+                    mv_list.push(ChessMove::new(from as u16, enpas_sq as u16, 0, 1));
                 }
             }
         }
 
+        let empty = !self.occ();
+
         // Single pushes
-        let push_shift = match self.turn {
-            ChessSide::White => 8,
-            ChessSide::Black => -8,
+        let push_dir = match self.turn {
+            ChessSide::White => Direction::North,
+            ChessSide::Black => Direction::South,
         };
-        let single_pushes = our_pawns.shift1(push_shift) & !self.side_bb[!self.turn];
+        let single_pushes = our_pawns.shift(push_dir) & empty;
         for to in single_pushes.lsb_iter() {
-            make_pawn_move((to as i8 - push_shift) as u16, to as u16, mv_list);
+            let from = (to as i8 - push_dir.offset()) as u8;
+            if legality.allows(from, to as u8) {
+                make_pawn_move(from as u16, to as u16, false, gen_type, mv_list);
+            }
         }
         // Double pushes
         let third_rank = match self.turn {
-            ChessSide::White => 0xFF0000,
-            ChessSide::Black => 0xFF00000000,
+            ChessSide::White => Bitboard::rank(2),
+            ChessSide::Black => Bitboard::rank(5),
         };
-        let double_pushes = (single_pushes & third_rank).shift1(push_shift);
+        let double_pushes = (single_pushes & third_rank).shift(push_dir) & empty;
         for to in double_pushes.lsb_iter() {
-            make_pawn_move((to as i8 - 2 * push_shift) as u16, to as u16, mv_list);
+            let from = (to as i8 - 2 * push_dir.offset()) as u8;
+            if legality.allows(from, to as u8) {
+                make_pawn_move(from as u16, to as u16, false, gen_type, mv_list);
+            }
         }
         // Captures
-        let capture_shifts = match self.turn {
-            ChessSide::White => [7, 9],
-            ChessSide::Black => [-7, -9],
+        let capture_dirs = match self.turn {
+            ChessSide::White => [Direction::NorthWest, Direction::NorthEast],
+            ChessSide::Black => [Direction::SouthEast, Direction::SouthWest],
         };
-        for shift in capture_shifts {
-            let captures = our_pawns.shift1(shift) & self.side_bb[!self.turn];
+        for dir in capture_dirs {
+            let captures = our_pawns.shift(dir) & self.side_bb[!self.turn];
             for to in captures.lsb_iter() {
-                make_pawn_move((to as i8 - shift) as u16, to as u16, mv_list);
+                let from = (to as i8 - dir.offset()) as u8;
+                if legality.allows(from, to as u8) {
+                    make_pawn_move(from as u16, to as u16, true, gen_type, mv_list);
+                }
             }
         }
     }
 
     #[inline]
-    pub fn gen_knight_moves(&self, mv_list: &mut Vec<ChessMove>, gen_type: GenType) {
-        let target_squares = match gen_type {
-            GenType::Quiet => !self.side_bb[self.turn],
-            GenType::Noisy => self.side_bb[!self.turn],
-        };
-        let knights = self.piece_bb[Piece::from((PieceType::Knight, self.turn))];
+    pub fn gen_knight_moves(
+        &self,
+        mv_list: &mut MoveList<ChessMove>,
+        gen_type: GenType,
+        legality: &Legality,
+    ) {
+        let target_squares = self.target_squares(gen_type) & legality.check_mask;
+        // A pinned knight can never move without leaving its line
+        let knights = self.piece_bb[Piece::from((PieceType::Knight, self.turn))] & !legality.pinned;
 
         for from in knights.lsb_iter() {
             let attacks = target_squares & get_knight_attacks(from as u8);
@@ -151,12 +617,14 @@ impl ChessBoard {
     }
 
     #[inline]
-    pub fn gen_king_moves(&self, mv_list: &mut Vec<ChessMove>, gen_type: GenType) {
-        let target_squares = match gen_type {
-            GenType::Quiet => !self.side_bb[self.turn] ^ self.side_bb[!self.turn],
-            GenType::Noisy => self.side_bb[!self.turn],
-        };
-        let from = self.piece_bb[Piece::from((PieceType::King, self.turn))].lsb() as u8;
+    pub fn gen_king_moves(
+        &self,
+        mv_list: &mut MoveList<ChessMove>,
+        gen_type: GenType,
+        legality: &Legality,
+    ) {
+        let target_squares = self.target_squares(gen_type) & !legality.enemy_attacks;
+        let from = legality.king_sq;
 
         let attacks = target_squares & get_king_attacks(from);
         for to in attacks.lsb_iter() {
@@ -165,18 +633,30 @@ impl ChessBoard {
     }
 
     #[inline]
-    pub fn gen_slider_moves(&self, mv_list: &mut Vec<ChessMove>, gen_type: GenType) {
-        let target_squares = match gen_type {
-            GenType::Quiet => !self.side_bb[self.turn] ^ self.side_bb[!self.turn],
-            GenType::Noisy => self.side_bb[!self.turn],
-        };
+    pub fn gen_slider_moves(
+        &self,
+        mv_list: &mut MoveList<ChessMove>,
+        gen_type: GenType,
+        legality: &Legality,
+    ) {
+        let target_squares = self.target_squares(gen_type) & legality.check_mask;
         let occupied_squares = self.side_bb[self.turn] | self.side_bb[!self.turn];
 
+        // Pinned sliders may only move along the line between our king and the pinner
+        let targets_from = |from: u8| {
+            if legality.pinned.is_set(from) {
+                target_squares & line(legality.king_sq, from)
+            } else {
+                target_squares
+            }
+        };
+
         // abstraction
         let bishops = self.piece_bb[Piece::from((PieceType::Bishop, self.turn))];
 
         for from in bishops.lsb_iter() {
-            let attacks = target_squares & get_bishop_attacks(from as u8, occupied_squares);
+            let attacks =
+                targets_from(from as u8) & get_bishop_attacks(from as u8, occupied_squares);
             for to in attacks.lsb_iter() {
                 mv_list.push(ChessMove::new(from as u16, to as u16, 0, 0));
             }
@@ -184,7 +664,7 @@ impl ChessBoard {
 
         let rooks = self.piece_bb[Piece::from((PieceType::Rook, self.turn))];
         for from in rooks.lsb_iter() {
-            let attacks = target_squares & get_rook_attacks(from as u8, occupied_squares);
+            let attacks = targets_from(from as u8) & get_rook_attacks(from as u8, occupied_squares);
             for to in attacks.lsb_iter() {
                 mv_list.push(ChessMove::new(from as u16, to as u16, 0, 0));
             }
@@ -192,7 +672,8 @@ impl ChessBoard {
 
         let queens = self.piece_bb[Piece::from((PieceType::Queen, self.turn))];
         for from in queens.lsb_iter() {
-            let attacks = target_squares & get_queen_attacks(from as u8, occupied_squares);
+            let attacks =
+                targets_from(from as u8) & get_queen_attacks(from as u8, occupied_squares);
             for to in attacks.lsb_iter() {
                 mv_list.push(ChessMove::new(from as u16, to as u16, 0, 0));
             }
@@ -200,34 +681,51 @@ impl ChessBoard {
     }
 
     #[inline]
-    pub fn gen_castle_moves(&self, mv_list: &mut Vec<ChessMove>, _gen_type: GenType) {
+    pub fn gen_castle_moves(
+        &self,
+        mv_list: &mut MoveList<ChessMove>,
+        gen_type: GenType,
+        legality: &Legality,
+    ) {
+        // Castling is quiet, and we can't castle out of check
+        if !gen_type.includes_quiet() || !legality.checkers.is_empty() {
+            return;
+        }
+
         let occ = self.occ();
+        let king_from = legality.king_sq;
+        let our_rook = Piece::from((PieceType::Rook, self.turn));
 
-        // Reminder that this is a pseudolegal move generator
-        match self.turn {
-            ChessSide::White => {
-                if self.castling_rights.has_right(CastlingRight::WhiteKing)
-                    && (occ & 0b00000110).is_empty()
-                {
-                    mv_list.push(ChessMove::new(4, 6, 0, 0));
-                }
-                if self.castling_rights.has_right(CastlingRight::WhiteQueen)
-                    && (occ & 0b01110000).is_empty()
-                {
-                    mv_list.push(ChessMove::new(4, 2, 0, 0));
-                }
+        for king_side in [true, false] {
+            let right = CastlingRight::new(self.turn, king_side);
+            let Some(rook_sq) = self.castling_rights.rook_square(right) else {
+                continue;
+            };
+            // A FEN can give a right without the king and rook being where it needs them
+            let rook_from = rook_sq.index();
+            if self.grid[rook_from as usize] != our_rook || king_from / 8 != rook_from / 8 {
+                continue;
             }
-            ChessSide::Black => {
-                if self.castling_rights.has_right(CastlingRight::BlackKing)
-                    && (occ & (0b00000110 << 56)).is_empty()
-                {
-                    mv_list.push(ChessMove::new(60, 62, 0, 0));
-                }
-                if self.castling_rights.has_right(CastlingRight::BlackQueen)
-                    && (occ & (0b01110000 << 56)).is_empty()
-                {
-                    mv_list.push(ChessMove::new(60, 58, 0, 0));
-                }
+
+            // Apart from the king and rook themselves, every square either of them passes
+            // through or lands on must be empty
+            let (king_to, rook_to) = castling_destinations(king_from, rook_from);
+            let others = occ ^ Bitboard::square(king_from) ^ Bitboard::square(rook_from);
+            let king_path = between(king_from, king_to) | Bitboard::square(king_to);
+            let rook_path = between(rook_from, rook_to) | Bitboard::square(rook_to);
+            if !((king_path | rook_path) & others).is_empty() {
+                continue;
+            }
+
+            // The king may not pass through or land on an attacked square. The rook is left out
+            // of the occupancy, since in Chess960 it can be shielding the king's destination.
+            let attacked = king_path.lsb_iter().any(|sq| {
+                !(self.attackers_to(Square::from_index(sq as u8), others)
+                    & self.side_bb[!self.turn])
+                    .is_empty()
+            });
+            if !attacked {
+                mv_list.push(ChessMove::new(king_from as u16, rook_from as u16, 0, 0));
             }
         }
     }
@@ -238,14 +736,14 @@ mod tests {
     use super::*;
     #[test]
     fn knight_moves() {
-        let board = ChessBoard::from_fen(
-            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string(),
-        );
+        let board =
+            ChessBoard::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")
+                .unwrap();
 
         board.print_board();
 
-        let mut move_list = Vec::new();
-        board.gen_knight_moves(&mut move_list, GenType::Quiet);
+        let mut move_list = MoveList::new();
+        board.gen_knight_moves(&mut move_list, GenType::Quiet, &board.legality());
         println!("{:?}", move_list);
 
         assert_eq!(move_list.len(), 4);
@@ -253,16 +751,341 @@ mod tests {
 
     #[test]
     fn king_moves() {
-        let board = ChessBoard::from_fen(
-            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string(),
-        );
+        let board =
+            ChessBoard::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")
+                .unwrap();
 
         board.print_board();
 
-        let mut move_list = Vec::new();
-        board.gen_king_moves(&mut move_list, GenType::Quiet);
+        let mut move_list = MoveList::new();
+        board.gen_king_moves(&mut move_list, GenType::Quiet, &board.legality());
         println!("{:?}", move_list);
 
         assert_eq!(move_list.len(), 0);
     }
+
+    fn legal_move_count(fen: &str) -> usize {
+        ChessBoard::from_fen(fen).unwrap().gen_legal().len()
+    }
+
+    #[test]
+    fn legal_move_counts() {
+        let cases = [
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                20,
+            ),
+            (
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                48,
+            ),
+            ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 14),
+            (
+                "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+                6,
+            ),
+            (
+                "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+                44,
+            ),
+            (
+                "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+                46,
+            ),
+        ];
+        for (fen, count) in cases {
+            assert_eq!(legal_move_count(fen), count, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn double_check_only_king_moves() {
+        // The king on e1 is checked by the knight on d3 and the rook on e8
+        let board = ChessBoard::from_fen("4r1k1/8/8/8/8/3n4/8/R3K2R w KQ - 0 1").unwrap();
+        let moves = board.gen_legal();
+
+        assert!(moves.iter().all(|mv| mv.from().index() == 4));
+        assert_eq!(moves.len(), 3);
+    }
+
+    #[test]
+    fn quiet_checks_in_double_check() {
+        // The king on e2 is checked by the rook on a2 and the knight on d4. Stepping off the e
+        // file uncovers the rook on e1, but staying on it doesn't.
+        let board = ChessBoard::from_fen("4k3/8/8/8/3n4/8/r3K3/4R3 w - - 0 1").unwrap();
+        let mut checks: Vec<_> = board
+            .gen_moves(GenType::QuietChecks)
+            .iter()
+            .map(|mv| mv.to_string())
+            .collect();
+        checks.sort();
+
+        assert_eq!(checks, ["e2d1", "e2d3", "e2f1"]);
+        assert!(board.gen_quiet().len() > checks.len());
+    }
+
+    #[test]
+    fn pinned_pieces() {
+        // The bishop on d2 is pinned by the bishop on a5 and may only move along the pin
+        let board = ChessBoard::from_fen("4k3/8/8/b7/8/8/3B4/4K3 w - - 0 1").unwrap();
+        let bishop_moves: Vec<_> = board
+            .gen_legal()
+            .into_iter()
+            .filter(|mv| mv.from().index() == 11)
+            .collect();
+
+        assert_eq!(bishop_moves.len(), 3);
+        assert!(bishop_moves
+            .iter()
+            .all(|mv| [18, 25, 32].contains(&mv.to().index())));
+    }
+
+    #[test]
+    fn no_castling_through_check() {
+        // The rook on f8 attacks f1, so only queenside castling is allowed
+        let board = ChessBoard::from_fen("4kr2/8/8/8/8/8/8/R3K2R w KQ - 0 1").unwrap();
+        let castles: Vec<_> = board
+            .gen_legal()
+            .into_iter()
+            .filter(|&mv| board.is_castling(mv))
+            .collect();
+
+        assert_eq!(castles, vec![ChessMove::new(4, 0, 0, 0)]);
+    }
+
+    #[test]
+    fn en_passant_discovered_check() {
+        // After c7c5 the white pawn on b5 could capture en passant, but that would leave the king
+        // on a5 in check from the rook on h5 along the rank
+        let mut board = ChessBoard::from_fen("8/2p5/8/KP5r/8/8/8/4k3 b - - 0 1").unwrap();
+        board.make_move(ChessMove::new(50, 34, 0, 0));
+
+        assert!(board.gen_legal().iter().all(|mv| !mv.is_en_passant()));
+
+        // Without the rook the capture is fine
+        let mut board = ChessBoard::from_fen("8/2p5/8/KP6/8/8/8/4k3 b - - 0 1").unwrap();
+        board.make_move(ChessMove::new(50, 34, 0, 0));
+
+        assert!(board.gen_legal().contains(&ChessMove::new(33, 42, 0, 1)));
+    }
+
+    #[test]
+    fn attackers_to_square() {
+        // e5 is attacked by the white pawn on d4, the knight on f3 and the rook on e1, while the
+        // black bishop on b8 is blocked by the pawn on c7 and the black queen on e7 is blocked by
+        // the knight on e6
+        let board = ChessBoard::from_fen("1b2k3/2p1q3/4n3/8/3P4/5N2/8/4RK2 w - - 0 1").unwrap();
+        let e5 = Square::parse("e5").unwrap();
+        let expected = ["d4", "f3", "e1"].iter().fold(Bitboard::empty(), |bb, sq| {
+            bb | Bitboard::square(Square::parse(sq).unwrap().index())
+        });
+        assert_eq!(board.attackers_to(e5, board.occ()), expected);
+
+        // Without the knight on e6 the queen attacks e5 too
+        let occ = board.occ() ^ Bitboard::square(44);
+        assert_eq!(board.attackers_to(e5, occ), expected | Bitboard::square(52));
+    }
+
+    /// The squares attacked by the piece on a square, given the occupancy
+    fn piece_attacks(board: &ChessBoard, sq: u8, occ: Bitboard) -> Bitboard {
+        let piece = board.grid[sq as usize];
+        match PieceType::from(piece) {
+            PieceType::Pawn => get_pawn_attacks(sq, ChessSide::from(piece)),
+            PieceType::Knight => get_knight_attacks(sq),
+            PieceType::Bishop => get_bishop_attacks(sq, occ),
+            PieceType::Rook => get_rook_attacks(sq, occ),
+            PieceType::Queen => get_bishop_attacks(sq, occ) | get_rook_attacks(sq, occ),
+            PieceType::King => get_king_attacks(sq),
+        }
+    }
+
+    /// Compare the cached checks and pins against working them out piece by piece
+    fn check_check_info(board: &ChessBoard) {
+        let fen = board.to_fen();
+        let occ = board.occ();
+        let king_sq = |side| board.piece_bb[Piece::from((PieceType::King, side))].lsb() as u8;
+        // Pieces of a side attacking the enemy king with the given occupancy
+        let attacking_king = |side: ChessSide, occ: Bitboard| {
+            let mut attackers = Bitboard::empty();
+            for sq in board.side_bb[side].lsb_iter() {
+                if occ.is_set(sq as u8)
+                    && piece_attacks(board, sq as u8, occ).is_set(king_sq(!side))
+                {
+                    attackers.set_bit(sq as u8);
+                }
+            }
+            attackers
+        };
+
+        assert_eq!(
+            board.checkers(),
+            attacking_king(!board.turn, occ),
+            "{}",
+            fen
+        );
+
+        for side in [ChessSide::White, ChessSide::Black] {
+            let mut blockers = Bitboard::empty();
+            let mut pinners = Bitboard::empty();
+            for sq in (occ & !Bitboard::square(king_sq(side))).lsb_iter() {
+                // Removing a blocker lets an enemy slider see the king
+                let mut without = occ;
+                without.clear_bit(sq as u8);
+                let uncovered = attacking_king(!side, without) & !attacking_king(!side, occ);
+                if !uncovered.is_empty() {
+                    blockers.set_bit(sq as u8);
+                    if board.side_bb[side].is_set(sq as u8) {
+                        pinners |= uncovered;
+                    }
+                }
+            }
+            assert_eq!(
+                board.blockers_for_king(side),
+                blockers,
+                "{} {:?}",
+                fen,
+                side
+            );
+            assert_eq!(
+                board.pinned(side),
+                blockers & board.side_bb[side],
+                "{}",
+                fen
+            );
+            assert_eq!(board.pinners(!side), pinners, "{} {:?}", fen, side);
+        }
+
+        for piece_type in [
+            PieceType::Pawn,
+            PieceType::Knight,
+            PieceType::Bishop,
+            PieceType::Rook,
+            PieceType::Queen,
+        ] {
+            let mut squares = Bitboard::empty();
+            for sq in 0..64 {
+                let mut board = board.clone();
+                board.grid[sq as usize] = Piece::from((piece_type, board.turn));
+                if piece_attacks(&board, sq, occ).is_set(king_sq(!board.turn)) {
+                    squares.set_bit(sq);
+                }
+            }
+            assert_eq!(
+                board.check_squares(piece_type),
+                squares,
+                "{} {:?}",
+                fen,
+                piece_type
+            );
+        }
+        assert!(board.check_squares(PieceType::King).is_empty(), "{}", fen);
+    }
+
+    fn check_check_info_tree(board: &mut ChessBoard, depth: u32) {
+        check_check_info(board);
+        if depth > 0 {
+            for mv in board.gen_legal() {
+                board.make_move(mv);
+                check_check_info_tree(board, depth - 1);
+                board.unmake_move(mv);
+            }
+            // Unmaking the moves should have restored the cached state
+            check_check_info(board);
+        }
+    }
+
+    #[test]
+    fn check_info_matches_brute_force() {
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "4k3/4r3/8/b7/8/4N3/3P4/r2QK2R w K - 0 1",
+        ] {
+            let mut board = ChessBoard::from_fen(fen).unwrap();
+            check_check_info_tree(&mut board, 2);
+        }
+    }
+
+    #[test]
+    fn cached_pins_and_check_squares() {
+        // The rook on e7 pins the knight on e3, the bishop on a5 pins the pawn on d2 and the
+        // rook on a1 pins the queen on d1
+        let board = ChessBoard::from_fen("4k3/4r3/8/b7/8/4N3/3P4/r2QK2R w K - 0 1").unwrap();
+        let squares = |names: &[&str]| {
+            let mut bb = Bitboard::empty();
+            for name in names {
+                bb.set_bit(Square::parse(name).unwrap().index());
+            }
+            bb
+        };
+
+        assert_eq!(board.checkers(), Bitboard::empty());
+        assert_eq!(board.pinned(ChessSide::White), squares(&["e3", "d2", "d1"]));
+        assert_eq!(
+            board.pinners(ChessSide::Black),
+            squares(&["e7", "a5", "a1"])
+        );
+        assert_eq!(board.blockers_for_king(ChessSide::Black), Bitboard::empty());
+        assert!(board
+            .check_squares(PieceType::Knight)
+            .is_set(Square::parse("d6").unwrap().index()));
+        assert!(board.check_squares(PieceType::King).is_empty());
+    }
+
+    #[test]
+    fn square_attacked_matches_attack_maps() {
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        ] {
+            let board = ChessBoard::from_fen(fen).unwrap();
+            for side in [ChessSide::White, ChessSide::Black] {
+                let attacks = board.attacked_squares(side, board.occ());
+                for sq in 0..64 {
+                    assert_eq!(
+                        board.is_square_attacked(Square::from_index(sq), side),
+                        attacks.is_set(sq),
+                        "{} {:?} {}",
+                        fen,
+                        side,
+                        Square::from_index(sq)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn move_accessors() {
+        let mv = ChessMove::new(52, 60, PieceType::Queen as u16, 0);
+        assert_eq!(mv.from(), Square::new(4, 6));
+        assert_eq!(mv.to(), Square::new(4, 7));
+        assert_eq!(mv.promotion(), Some(PieceType::Queen));
+        assert!(!mv.is_en_passant());
+
+        let mv = ChessMove::new(36, 43, 0, 1);
+        assert_eq!(mv.promotion(), None);
+        assert!(mv.is_en_passant());
+    }
+
+    #[test]
+    fn uci_display() {
+        assert_eq!(ChessMove::new(12, 28, 0, 0).to_string(), "e2e4");
+        assert_eq!(ChessMove::new(4, 6, 0, 0).to_string(), "e1g1");
+        assert_eq!(
+            ChessMove::new(52, 60, PieceType::Queen as u16, 0).to_string(),
+            "e7e8q"
+        );
+        assert_eq!(
+            ChessMove::new(9, 0, PieceType::Knight as u16, 0).to_string(),
+            "b2a1n"
+        );
+        assert_eq!(
+            format!("{:?}", ChessMove::new(36, 43, 0, 1)),
+            "e5d6 (en passant)"
+        );
+    }
 }
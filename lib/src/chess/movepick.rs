@@ -0,0 +1,297 @@
+use super::board::ChessBoard;
+use super::eval::PIECE_VALUES;
+use super::history::{History, PieceTo};
+use super::movegen::ChessMove;
+use super::types::*;
+use crate::movelist::MoveList;
+use crate::types::*;
+
+/// The stages of a `MovePicker`, in the order they are visited
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Stage {
+    HashMove,
+    GenNoisy,
+    GoodNoisy,
+    Killers,
+    Countermove,
+    GenQuiet,
+    Quiet,
+    BadNoisy,
+    Done,
+}
+
+/// Yields the legal moves of a position in the order a search wants to try them: the hash move,
+/// then captures which don't lose material, then killer moves and the countermove, then the
+/// remaining quiet moves by their history scores, and finally the captures which lose material.
+/// Each group is only generated once the previous ones are used up, so a cutoff early on skips
+/// generating quiet moves at all.
+pub struct MovePicker {
+    hash_move: Option<ChessMove>,
+    killers: [Option<ChessMove>; 2],
+    countermove: Option<ChessMove>,
+    prev: Option<PieceTo>,
+    stage: Stage,
+    noisy: MoveList<ChessMove>,
+    bad_noisy: MoveList<ChessMove>,
+    quiet: MoveList<ChessMove>,
+    index: usize,
+}
+
+impl MovePicker {
+    /// Create a move picker for a position, given the move played before it if there was one.
+    /// The hash move, killers and countermove don't need to be legal in the position, since they
+    /// are checked before being returned.
+    pub fn new(
+        hash_move: Option<ChessMove>,
+        killers: [Option<ChessMove>; 2],
+        countermove: Option<ChessMove>,
+        prev: Option<PieceTo>,
+    ) -> Self {
+        MovePicker {
+            hash_move,
+            killers,
+            countermove,
+            prev,
+            stage: Stage::HashMove,
+            noisy: MoveList::new(),
+            bad_noisy: MoveList::new(),
+            quiet: MoveList::new(),
+            index: 0,
+        }
+    }
+
+    /// Return the next move to try, or `None` once every legal move was returned. The board must
+    /// be in the position the picker was created for.
+    pub fn next(&mut self, board: &ChessBoard, history: &History) -> Option<ChessMove> {
+        loop {
+            match self.stage {
+                Stage::HashMove => {
+                    self.stage = Stage::GenNoisy;
+                    if let Some(mv) = self.hash_move.filter(|&mv| board.is_legal(mv)) {
+                        return Some(mv);
+                    }
+                }
+                Stage::GenNoisy => {
+                    self.noisy = board.gen_noisy();
+                    for i in 0..self.noisy.len() {
+                        let score = mvv_lva(board, self.noisy[i]);
+                        self.noisy.set_score(i, score);
+                    }
+                    self.index = 0;
+                    self.stage = Stage::GoodNoisy;
+                }
+                Stage::GoodNoisy => {
+                    if let Some(mv) = self.noisy.pick_best(self.index) {
+                        self.index += 1;
+                        if self.already_picked(mv) {
+                            continue;
+                        }
+                        // Captures which lose material wait until after the quiet moves
+                        if !board.see_ge(mv, 0) {
+                            self.bad_noisy.push(mv);
+                            continue;
+                        }
+                        return Some(mv);
+                    } else {
+                        self.index = 0;
+                        self.stage = Stage::Killers;
+                    }
+                }
+                Stage::Killers => {
+                    if self.index < self.killers.len() {
+                        let killer = self.killers[self.index];
+                        self.index += 1;
+                        // The second killer may be a copy of the first
+                        let repeated = self.index == 2 && killer == self.killers[0];
+                        if let Some(mv) =
+                            killer.filter(|&mv| !repeated && self.is_new_quiet(board, mv))
+                        {
+                            return Some(mv);
+                        }
+                    } else {
+                        self.stage = Stage::Countermove;
+                    }
+                }
+                Stage::Countermove => {
+                    self.stage = Stage::GenQuiet;
+                    if let Some(mv) = self.countermove.filter(|&mv| self.is_new_quiet(board, mv)) {
+                        return Some(mv);
+                    }
+                }
+                Stage::GenQuiet => {
+                    self.quiet = board.gen_quiet();
+                    for i in 0..self.quiet.len() {
+                        let score = history.quiet_score(board, self.quiet[i], self.prev);
+                        self.quiet.set_score(i, score);
+                    }
+                    self.index = 0;
+                    self.stage = Stage::Quiet;
+                }
+                Stage::Quiet => {
+                    if let Some(mv) = self.quiet.pick_best(self.index) {
+                        self.index += 1;
+                        if !self.already_picked(mv) {
+                            return Some(mv);
+                        }
+                    } else {
+                        self.index = 0;
+                        self.stage = Stage::BadNoisy;
+                    }
+                }
+                Stage::BadNoisy => {
+                    // These were deferred in order, so they are still best first
+                    if let Some(&mv) = self.bad_noisy.get(self.index) {
+                        self.index += 1;
+                        return Some(mv);
+                    } else {
+                        self.stage = Stage::Done;
+                    }
+                }
+                Stage::Done => return None,
+            }
+        }
+    }
+
+    /// Return true if the move was already returned by an earlier stage
+    fn already_picked(&self, mv: ChessMove) -> bool {
+        let killer = self.killers.contains(&Some(mv));
+        self.hash_move == Some(mv)
+            || match self.stage {
+                Stage::Countermove => killer,
+                Stage::Quiet => killer || self.countermove == Some(mv),
+                _ => false,
+            }
+    }
+
+    /// Return true if a killer or countermove should be returned. A move which captures here was
+    /// already tried with the noisy moves.
+    fn is_new_quiet(&self, board: &ChessBoard, mv: ChessMove) -> bool {
+        !self.already_picked(mv) && !board.is_noisy(mv) && board.is_legal(mv)
+    }
+}
+
+/// The material a move wins before any recapture, counting the captured piece and the gain from
+/// promoting
+pub(super) fn material_gain(board: &ChessBoard, mv: ChessMove) -> i32 {
+    let captured = if mv.is_en_passant() {
+        PIECE_VALUES[PieceType::Pawn as usize]
+    } else if board.is_capture(mv) {
+        PIECE_VALUES[PieceType::from(board.piece_on(mv.to())) as usize]
+    } else {
+        0
+    };
+    let promotion = mv.promotion().map_or(0, |pt| {
+        PIECE_VALUES[pt as usize] - PIECE_VALUES[PieceType::Pawn as usize]
+    });
+    captured + promotion
+}
+
+/// Most valuable victim, least valuable attacker: captures of the most valuable pieces come
+/// first, and among those the ones made with the least valuable piece. Moves which win nothing,
+/// such as quiet check evasions, come last.
+pub(super) fn mvv_lva(board: &ChessBoard, mv: ChessMove) -> i32 {
+    let gain = material_gain(board, mv);
+    if gain == 0 {
+        return 0;
+    }
+    let attacker = PieceType::from(board.piece_on(mv.from()));
+    gain * 8 - attacker as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FENS: [&str; 6] = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    ];
+
+    fn uci(board: &ChessBoard, mv: &str) -> Option<ChessMove> {
+        Some(board.parse_uci_move(mv).unwrap())
+    }
+
+    fn pick_all(board: &ChessBoard, mut picker: MovePicker, history: &History) -> Vec<ChessMove> {
+        let mut picked = Vec::new();
+        while let Some(mv) = picker.next(board, history) {
+            picked.push(mv);
+        }
+        picked
+    }
+
+    #[test]
+    fn picks_every_legal_move_once() {
+        let history = History::new();
+        for fen in FENS {
+            let board = ChessBoard::from_fen(fen).unwrap();
+            let legal = board.gen_legal();
+            let hash_move = legal.last().copied();
+            let killers = [legal.first().copied(), Some(ChessMove::new(0, 63, 0, 0))];
+            let countermove = legal.get(1).copied();
+
+            let picker = MovePicker::new(hash_move, killers, countermove, None);
+            let picked = pick_all(&board, picker, &history);
+            assert_eq!(picked.len(), legal.len(), "{}", fen);
+            assert!(legal.iter().all(|mv| picked.contains(mv)), "{}", fen);
+            assert_eq!(picked.first().copied(), hash_move, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn stage_order() {
+        let board = ChessBoard::from_fen(FENS[1]).unwrap();
+        let hash_move = uci(&board, "e2a6");
+        // The king can't move to e2, since the bishop is there
+        let killers = [uci(&board, "c3b1"), Some(ChessMove::new(4, 12, 0, 0))];
+        let countermove = uci(&board, "a2a3");
+
+        // Give one quiet move a good history score
+        let mut history = History::new();
+        history.update(&board, uci(&board, "g2g3").unwrap(), &[], None, 0, 8);
+
+        let picker = MovePicker::new(hash_move, killers, countermove, None);
+        let picked = pick_all(&board, picker, &history);
+
+        // The captures which don't lose material come right after the hash move, best first,
+        // followed by the legal killer, the countermove and the quiet move with the best history
+        assert_eq!(Some(picked[0]), hash_move);
+        let split = picked
+            .iter()
+            .position(|&mv| Some(mv) == killers[0])
+            .unwrap();
+        let good = &picked[1..split];
+        assert!(good
+            .iter()
+            .all(|&mv| board.is_noisy(mv) && board.see_ge(mv, 0)));
+        assert!(good
+            .windows(2)
+            .all(|w| mvv_lva(&board, w[0]) >= mvv_lva(&board, w[1])));
+        assert_eq!(Some(picked[split + 1]), countermove);
+        assert_eq!(Some(picked[split + 2]), uci(&board, "g2g3"));
+
+        // The captures which lose material come last, such as Qxf6 giving up the queen for a
+        // knight
+        let bad: Vec<_> = picked
+            .iter()
+            .rev()
+            .take_while(|&&mv| board.is_noisy(mv))
+            .collect();
+        assert!(bad.iter().all(|&&mv| !board.see_ge(mv, 0)));
+        assert!(bad.contains(&&uci(&board, "f3f6").unwrap()));
+        assert!(!picked.contains(&ChessMove::new(4, 12, 0, 0)));
+    }
+
+    #[test]
+    fn mvv_lva_order() {
+        let board = ChessBoard::from_fen("4k3/8/2q1r3/3P2N1/8/8/8/K7 w - - 0 1").unwrap();
+        let mv = |s| board.parse_uci_move(s).unwrap();
+        assert!(mvv_lva(&board, mv("d5c6")) > mvv_lva(&board, mv("d5e6")));
+        assert!(mvv_lva(&board, mv("d5e6")) > mvv_lva(&board, mv("g5e6")));
+        assert!(mvv_lva(&board, mv("g5e6")) > 0);
+        assert_eq!(mvv_lva(&board, mv("a1b1")), 0);
+    }
+}
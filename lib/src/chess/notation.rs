@@ -0,0 +1,496 @@
+//! Reading and writing moves as text, in UCI notation and Standard Algebraic Notation (SAN)
+
+use super::board::{castling_destinations, ChessBoard, Square};
+use super::movegen::ChessMove;
+use super::types::{Piece, PieceType};
+use crate::types::*;
+use std::fmt;
+
+/// The ways a move in text can fail to match a move in the position
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MoveParseError {
+    /// The text is not a move in the notation
+    InvalidSyntax,
+    /// The text describes a move which isn't legal in the position
+    IllegalMove,
+    /// The text could describe more than one legal move
+    AmbiguousMove,
+}
+
+impl fmt::Display for MoveParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            MoveParseError::InvalidSyntax => "invalid move syntax",
+            MoveParseError::IllegalMove => "illegal move",
+            MoveParseError::AmbiguousMove => "ambiguous move",
+        })
+    }
+}
+
+impl std::error::Error for MoveParseError {}
+
+impl ChessBoard {
+    /// Parse a move in the long algebraic notation used by UCI, such as "e2e4" or "e7e8q". The
+    /// move is looked up among the legal moves, so that it has the right flags for things like
+    /// en passant. Castling may be written as the king capturing its own rook, and outside of
+    /// Chess960 also as the king moving two squares.
+    pub fn parse_uci_move(&self, s: &str) -> Result<ChessMove, MoveParseError> {
+        if !s.is_ascii() || !(4..=5).contains(&s.len()) {
+            return Err(MoveParseError::InvalidSyntax);
+        }
+
+        let from = Square::parse(&s[0..2]).ok_or(MoveParseError::InvalidSyntax)?;
+        let to = Square::parse(&s[2..4]).ok_or(MoveParseError::InvalidSyntax)?;
+        let promotion = match &s[4..] {
+            "" => None,
+            "n" => Some(PieceType::Knight),
+            "b" => Some(PieceType::Bishop),
+            "r" => Some(PieceType::Rook),
+            "q" => Some(PieceType::Queen),
+            _ => return Err(MoveParseError::InvalidSyntax),
+        };
+
+        self.gen_legal()
+            .into_iter()
+            .find(|&mv| {
+                mv.from() == from
+                    && (mv.to() == to || self.uci_to(mv) == to)
+                    && mv.promotion() == promotion
+            })
+            .ok_or(MoveParseError::IllegalMove)
+    }
+
+    /// Write a legal move in UCI notation. Castling is written as the king moving two squares,
+    /// such as "e1g1", except in Chess960 where it is written as the king capturing its rook.
+    pub fn to_uci(&self, mv: ChessMove) -> String {
+        let to = self.uci_to(mv);
+        if to == mv.to() {
+            mv.to_string()
+        } else {
+            ChessMove::new(mv.from().index() as u16, to.index() as u16, 0, 0).to_string()
+        }
+    }
+
+    /// The destination square of a move as UCI writes it
+    fn uci_to(&self, mv: ChessMove) -> Square {
+        if self.is_castling(mv) && !self.chess960 {
+            let (king_to, _) = castling_destinations(mv.from().index(), mv.to().index());
+            Square::from_index(king_to)
+        } else {
+            mv.to()
+        }
+    }
+
+    /// Write a legal move in Standard Algebraic Notation, such as "Nbd7", "exd6", "O-O-O",
+    /// "e8=Q+" or "Qxf7#". Pieces are only disambiguated by as much as they need to be.
+    pub fn to_san(&self, mv: ChessMove) -> String {
+        let mut san = String::new();
+
+        if self.is_castling(mv) {
+            san.push_str(if mv.to().file() > mv.from().file() {
+                "O-O"
+            } else {
+                "O-O-O"
+            });
+        } else {
+            let piece_type = PieceType::from(self.piece_on(mv.from()));
+            let capture = self.is_capture(mv);
+
+            if piece_type == PieceType::Pawn {
+                if capture {
+                    san.push(file_char(mv.from()));
+                }
+            } else {
+                san.push(piece_char(piece_type));
+
+                // Other pieces of the same type which could also move to the same square
+                let others: Vec<Square> = self
+                    .gen_legal()
+                    .into_iter()
+                    .filter(|other| {
+                        other.to() == mv.to()
+                            && other.from() != mv.from()
+                            && self.piece_on(other.from()) == self.piece_on(mv.from())
+                    })
+                    .map(|other| other.from())
+                    .collect();
+                if !others.is_empty() {
+                    if others.iter().all(|sq| sq.file() != mv.from().file()) {
+                        san.push(file_char(mv.from()));
+                    } else if others.iter().all(|sq| sq.rank() != mv.from().rank()) {
+                        san.push(rank_char(mv.from()));
+                    } else {
+                        san.push(file_char(mv.from()));
+                        san.push(rank_char(mv.from()));
+                    }
+                }
+            }
+
+            if capture {
+                san.push('x');
+            }
+            san.push_str(&mv.to().to_string());
+
+            if let Some(promotion) = mv.promotion() {
+                san.push('=');
+                san.push(piece_char(promotion));
+            }
+        }
+
+        let mut after = self.clone();
+        after.make_move(mv);
+        if after.in_check() {
+            san.push(if after.gen_legal().is_empty() {
+                '#'
+            } else {
+                '+'
+            });
+        }
+
+        san
+    }
+
+    /// Parse a move in Standard Algebraic Notation. Common variations are accepted too: castling
+    /// with zeros, a missing 'x' on captures, promotions without the '=' and trailing check
+    /// marks and annotations like "!?".
+    pub fn parse_san(&self, s: &str) -> Result<ChessMove, MoveParseError> {
+        let s = s.trim_end_matches(['+', '#', '!', '?']);
+        if !s.is_ascii() || s.is_empty() {
+            return Err(MoveParseError::InvalidSyntax);
+        }
+
+        let castle = match s {
+            "O-O" | "0-0" => Some(true),
+            "O-O-O" | "0-0-0" => Some(false),
+            _ => None,
+        };
+        if let Some(king_side) = castle {
+            return self
+                .gen_legal()
+                .into_iter()
+                .find(|&mv| {
+                    self.is_castling(mv) && (mv.to().file() > mv.from().file()) == king_side
+                })
+                .ok_or(MoveParseError::IllegalMove);
+        }
+
+        let mut rest = s.as_bytes();
+        let piece_type = match rest[0] {
+            b'N' => PieceType::Knight,
+            b'B' => PieceType::Bishop,
+            b'R' => PieceType::Rook,
+            b'Q' => PieceType::Queen,
+            b'K' => PieceType::King,
+            _ => PieceType::Pawn,
+        };
+        if piece_type != PieceType::Pawn {
+            rest = &rest[1..];
+        }
+
+        // Promotions, with or without the '='
+        let mut promotion = None;
+        if let (PieceType::Pawn, [init @ .., last]) = (piece_type, rest) {
+            promotion = match last.to_ascii_uppercase() {
+                b'N' => Some(PieceType::Knight),
+                b'B' => Some(PieceType::Bishop),
+                b'R' => Some(PieceType::Rook),
+                b'Q' => Some(PieceType::Queen),
+                _ => None,
+            };
+            if promotion.is_some() {
+                rest = init.strip_suffix(b"=").unwrap_or(init);
+            }
+        }
+
+        let (rest, to) = match rest {
+            [rest @ .., file, rank] => (rest, [*file, *rank]),
+            _ => return Err(MoveParseError::InvalidSyntax),
+        };
+        let to = std::str::from_utf8(&to)
+            .ok()
+            .and_then(Square::parse)
+            .ok_or(MoveParseError::InvalidSyntax)?;
+        let rest = rest
+            .strip_suffix(b"x")
+            .or_else(|| rest.strip_suffix(b":"))
+            .unwrap_or(rest);
+
+        // Whatever is left disambiguates the piece by its file, rank or both
+        let (from_file, from_rank) = match rest {
+            [] => (None, None),
+            [file @ b'a'..=b'h'] => (Some(file - b'a'), None),
+            [rank @ b'1'..=b'8'] => (None, Some(rank - b'1')),
+            [file @ b'a'..=b'h', rank @ b'1'..=b'8'] => (Some(file - b'a'), Some(rank - b'1')),
+            _ => return Err(MoveParseError::InvalidSyntax),
+        };
+
+        let mut candidates = self.gen_legal().into_iter().filter(|&mv| {
+            mv.to() == to
+                && mv.promotion() == promotion
+                && self.piece_on(mv.from()) == Piece::from((piece_type, self.turn))
+                && !self.is_castling(mv)
+                && from_file.is_none_or(|file| mv.from().file() == file)
+                && from_rank.is_none_or(|rank| mv.from().rank() == rank)
+        });
+        match (candidates.next(), candidates.next()) {
+            (Some(mv), None) => Ok(mv),
+            (Some(_), Some(_)) => Err(MoveParseError::AmbiguousMove),
+            (None, _) => Err(MoveParseError::IllegalMove),
+        }
+    }
+}
+
+fn piece_char(piece_type: PieceType) -> char {
+    match piece_type {
+        PieceType::Pawn => 'P',
+        PieceType::Knight => 'N',
+        PieceType::Bishop => 'B',
+        PieceType::Rook => 'R',
+        PieceType::Queen => 'Q',
+        PieceType::King => 'K',
+    }
+}
+
+fn file_char(sq: Square) -> char {
+    (b'a' + sq.file()) as char
+}
+
+fn rank_char(sq: Square) -> char {
+    (b'1' + sq.rank()) as char
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn board(fen: &str) -> ChessBoard {
+        ChessBoard::from_fen(fen).unwrap()
+    }
+
+    #[test]
+    fn parse_uci() {
+        let start = board("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        assert_eq!(
+            start.parse_uci_move("e2e4"),
+            Ok(ChessMove::new(12, 28, 0, 0))
+        );
+        assert_eq!(
+            start.parse_uci_move("g1f3"),
+            Ok(ChessMove::new(6, 21, 0, 0))
+        );
+
+        let special = board("4k3/8/8/3pP3/8/8/8/R3K2R w KQ d6 0 1");
+        assert_eq!(
+            special.parse_uci_move("e5d6"),
+            Ok(ChessMove::new(36, 43, 0, 1))
+        );
+        // Castling is stored as the king capturing its rook, which is accepted too
+        assert_eq!(
+            special.parse_uci_move("e1g1"),
+            Ok(ChessMove::new(4, 7, 0, 0))
+        );
+        assert_eq!(
+            special.parse_uci_move("e1c1"),
+            Ok(ChessMove::new(4, 0, 0, 0))
+        );
+        assert_eq!(
+            special.parse_uci_move("e1h1"),
+            Ok(ChessMove::new(4, 7, 0, 0))
+        );
+
+        let promotion = board("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1");
+        assert_eq!(
+            promotion.parse_uci_move("b7b8n"),
+            Ok(ChessMove::new(49, 57, PieceType::Knight as u16, 0))
+        );
+    }
+
+    #[test]
+    fn parse_uci_errors() {
+        let start = board("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        for s in ["", "e2", "e2e4e", "e2e9", "i2e4", "e2e4qq", "é2e4"] {
+            assert_eq!(
+                start.parse_uci_move(s),
+                Err(MoveParseError::InvalidSyntax),
+                "{}",
+                s
+            );
+        }
+        for s in ["e2e5", "e7e5", "e1g1", "e2e4q"] {
+            assert_eq!(
+                start.parse_uci_move(s),
+                Err(MoveParseError::IllegalMove),
+                "{}",
+                s
+            );
+        }
+    }
+
+    #[test]
+    fn chess960_uci() {
+        // The king on b1 castles queen side with the rook on a1, ending up on c1 with the rook
+        // on d1, so only king-captures-rook notation can describe it
+        let mut chess960 = board("r3k3/8/8/8/8/8/8/RK5R w HAa - 0 1");
+        assert!(chess960.chess960);
+        let castle = chess960.parse_uci_move("b1a1").unwrap();
+        assert!(chess960.is_castling(castle));
+        assert_eq!(chess960.to_uci(castle), "b1a1");
+        assert_eq!(chess960.to_san(castle), "O-O-O");
+        assert_eq!(
+            chess960.parse_uci_move("b1c1"),
+            Ok(ChessMove::new(1, 2, 0, 0))
+        );
+
+        chess960.make_move(castle);
+        assert_eq!(chess960.to_fen(), "r3k3/8/8/8/8/8/8/2KR3R b q - 1 1");
+
+        // In standard chess castling is written as the king moving two squares
+        let standard = board("4k3/8/8/8/8/8/8/R3K2R w KQ - 0 1");
+        assert!(!standard.chess960);
+        assert_eq!(standard.to_uci(ChessMove::new(4, 7, 0, 0)), "e1g1");
+        assert_eq!(standard.to_uci(ChessMove::new(4, 0, 0, 0)), "e1c1");
+    }
+
+    #[test]
+    fn uci_round_trip() {
+        let board = board("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        for mv in board.gen_legal() {
+            assert_eq!(board.parse_uci_move(&mv.to_string()), Ok(mv));
+        }
+    }
+
+    #[test]
+    fn san_formatting() {
+        let cases = [
+            // Knights on b8 and f6 can both reach d7
+            (
+                "rnbqkb1r/ppp2ppp/5n2/3pp3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 0 1",
+                "b8d7",
+                "Nbd7",
+            ),
+            ("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6", "exd6"),
+            ("r3k3/8/8/8/8/8/8/4K3 b q - 0 1", "e8c8", "O-O-O"),
+            ("4k2r/8/8/8/8/8/8/4K3 b k - 0 1", "e8g8", "O-O"),
+            ("3k4/4P3/8/8/8/8/8/4K3 w - - 0 1", "e7e8q", "e8=Q+"),
+            ("3k4/4P3/8/8/8/8/8/4K3 w - - 0 1", "e7e8n", "e8=N"),
+            // Scholar's mate
+            (
+                "r1bqkbnr/pppp1ppp/2n5/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 0 1",
+                "h5f7",
+                "Qxf7#",
+            ),
+            // Rooks on a1 and a5 share a file, so the rank is used
+            ("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1", "a1a3", "R1a3"),
+            // Queens on e4, h4 and h1 need both the file and rank for the one on h4
+            ("8/2k5/8/8/4Q2Q/8/8/K6Q w - - 0 1", "h4e1", "Qh4e1"),
+            ("4k3/8/8/8/8/8/8/R3K2R w - - 0 1", "e1f2", "Kf2"),
+        ];
+
+        for (fen, uci, san) in cases {
+            let board = board(fen);
+            let mv = board.parse_uci_move(uci).expect(uci);
+            assert_eq!(board.to_san(mv), san, "{} {}", fen, uci);
+        }
+    }
+
+    #[test]
+    fn san_parsing() {
+        let cases = [
+            (
+                "rnbqkb1r/ppp2ppp/5n2/3pp3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 0 1",
+                "Nbd7",
+                "b8d7",
+            ),
+            (
+                "rnbqkb1r/ppp2ppp/5n2/3pp3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 0 1",
+                "Nb8d7",
+                "b8d7",
+            ),
+            (
+                "rnbqkb1r/ppp2ppp/5n2/3pp3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 0 1",
+                "Nfd7!?",
+                "f6d7",
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                "e4",
+                "e2e4",
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                "Nf3",
+                "g1f3",
+            ),
+            ("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "exd6", "e5d6"),
+            ("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "ed6", "e5d6"),
+            ("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5xd6", "e5d6"),
+            ("r3k3/8/8/8/8/8/8/4K3 b q - 0 1", "O-O-O", "e8c8"),
+            ("r3k3/8/8/8/8/8/8/4K3 b q - 0 1", "0-0-0", "e8c8"),
+            ("4k2r/8/8/8/8/8/8/4K3 b k - 0 1", "0-0+", "e8g8"),
+            ("3k4/4P3/8/8/8/8/8/4K3 w - - 0 1", "e8=Q+", "e7e8q"),
+            ("3k4/4P3/8/8/8/8/8/4K3 w - - 0 1", "e8Q", "e7e8q"),
+            ("3k4/4P3/8/8/8/8/8/4K3 w - - 0 1", "e8n", "e7e8n"),
+            (
+                "r1bqkbnr/pppp1ppp/2n5/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 0 1",
+                "Qxf7#",
+                "h5f7",
+            ),
+            (
+                "r1bqkbnr/pppp1ppp/2n5/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 0 1",
+                "Qf7",
+                "h5f7",
+            ),
+            ("4k3/8/8/R7/8/8/8/R3K3 w - - 0 1", "R1a3", "a1a3"),
+            ("8/2k5/8/8/4Q2Q/8/8/K6Q w - - 0 1", "Qh4e1", "h4e1"),
+            // A lower case b is a pawn, and an upper case B a bishop
+            ("4k3/8/8/8/2p5/1P6/8/4KB2 w - - 0 1", "bxc4", "b3c4"),
+            ("4k3/8/8/8/2p5/1P6/8/4KB2 w - - 0 1", "Bxc4", "f1c4"),
+        ];
+
+        for (fen, san, uci) in cases {
+            let board = board(fen);
+            assert_eq!(
+                board.parse_san(san),
+                board.parse_uci_move(uci),
+                "{} {}",
+                fen,
+                san
+            );
+        }
+    }
+
+    #[test]
+    fn san_parse_errors() {
+        let knights = board("rnbqkb1r/ppp2ppp/5n2/3pp3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 0 1");
+        assert_eq!(knights.parse_san("Nd7"), Err(MoveParseError::AmbiguousMove));
+        assert_eq!(knights.parse_san("Nd5"), Err(MoveParseError::IllegalMove));
+        assert_eq!(knights.parse_san("O-O"), Err(MoveParseError::IllegalMove));
+        assert_eq!(knights.parse_san("e9"), Err(MoveParseError::InvalidSyntax));
+        assert_eq!(
+            knights.parse_san("Nzd7"),
+            Err(MoveParseError::InvalidSyntax)
+        );
+        assert_eq!(knights.parse_san(""), Err(MoveParseError::InvalidSyntax));
+        assert_eq!(knights.parse_san("+"), Err(MoveParseError::InvalidSyntax));
+
+        // Promotions have to say what they promote to
+        let promotion = board("3k4/4P3/8/8/8/8/8/4K3 w - - 0 1");
+        assert_eq!(promotion.parse_san("e8"), Err(MoveParseError::IllegalMove));
+    }
+
+    #[test]
+    fn san_round_trip() {
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "8/2k5/8/8/4Q2Q/8/8/K6Q w - - 0 1",
+        ];
+        for fen in fens {
+            let board = board(fen);
+            for mv in board.gen_legal() {
+                assert_eq!(board.parse_san(&board.to_san(mv)), Ok(mv), "{}", fen);
+            }
+        }
+    }
+}
@@ -0,0 +1,241 @@
+//! Perft counts the leaf nodes of the legal move tree to a fixed depth. Comparing the counts
+//! against known values is the standard way of checking a move generator.
+
+use super::board::ChessBoard;
+use crate::types::*;
+
+/// Count the number of leaf nodes `depth` moves deep from the position
+pub fn perft(board: &mut ChessBoard, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+
+    let moves = board.gen_legal();
+    // The moves at the last ply don't need to be played to be counted
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+
+    let mut nodes = 0;
+    for mv in moves {
+        board.make_move(mv);
+        nodes += perft(board, depth - 1);
+        board.unmake_move(mv);
+    }
+    nodes
+}
+
+/// Run perft, printing the node count under each root move. This makes it easy to find which
+/// move a generator disagrees on when comparing against another engine.
+pub fn divide(board: &mut ChessBoard, depth: u32) -> u64 {
+    let mut nodes = 0;
+    for mv in board.gen_legal() {
+        let uci = board.to_uci(mv);
+        board.make_move(mv);
+        let count = perft(board, depth.saturating_sub(1));
+        board.unmake_move(mv);
+
+        println!("{}: {}", uci, count);
+        nodes += count;
+    }
+    println!();
+    println!("Nodes searched: {}", nodes);
+    nodes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chess::{ChessMove, GenType};
+
+    const STARTPOS: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+    const POSITION_3: &str = "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1";
+    const POSITION_4: &str = "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1";
+    const POSITION_4_MIRRORED: &str =
+        "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1";
+    const POSITION_5: &str = "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8";
+    const POSITION_6: &str =
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10";
+
+    fn check(fen: &str, counts: &[u64]) {
+        let mut board = ChessBoard::from_fen(fen).unwrap();
+        for (depth, &count) in counts.iter().enumerate() {
+            assert_eq!(
+                perft(&mut board, depth as u32 + 1),
+                count,
+                "depth {} of {}",
+                depth + 1,
+                fen
+            );
+        }
+    }
+
+    #[test]
+    fn startpos() {
+        check(STARTPOS, &[20, 400, 8902]);
+    }
+
+    #[test]
+    fn kiwipete() {
+        check(KIWIPETE, &[48, 2039]);
+    }
+
+    #[test]
+    fn position_3() {
+        check(POSITION_3, &[14, 191, 2812, 43238]);
+    }
+
+    #[test]
+    fn position_4() {
+        check(POSITION_4, &[6, 264, 9467]);
+        check(POSITION_4_MIRRORED, &[6, 264, 9467]);
+    }
+
+    #[test]
+    fn position_5() {
+        check(POSITION_5, &[44, 1486, 62379]);
+    }
+
+    #[test]
+    fn position_6() {
+        check(POSITION_6, &[46, 2079, 89890]);
+    }
+
+    // Chess960 positions, with castling rights in Shredder-FEN
+    const CHESS960: [(&str, [u64; 4]); 3] = [
+        (
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+            [21, 528, 12189, 326672],
+        ),
+        (
+            "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9",
+            [21, 807, 18002, 667366],
+        ),
+        (
+            "b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9",
+            [20, 479, 10471, 273318],
+        ),
+    ];
+
+    #[test]
+    fn chess960() {
+        for (fen, counts) in CHESS960 {
+            check(fen, &counts[..3]);
+        }
+
+        // Position 518 is the standard starting position
+        let mut board = ChessBoard::from_chess960_index(518).unwrap();
+        assert_eq!(perft(&mut board, 3), 8902);
+    }
+
+    #[test]
+    fn divide_sums_to_perft() {
+        let mut board = ChessBoard::from_fen(KIWIPETE).unwrap();
+        assert_eq!(divide(&mut board, 2), 2039);
+    }
+
+    /// Check the generation types against the full move list at every node of the tree
+    fn check_gen_types(board: &mut ChessBoard, depth: u32) {
+        let legal = board.gen_legal();
+        let noisy = board.gen_noisy();
+        let quiet = board.gen_quiet();
+        let fen = board.to_fen();
+
+        assert_eq!(noisy.len() + quiet.len(), legal.len(), "{}", fen);
+        assert!(noisy.iter().all(|mv| !quiet.contains(mv)), "{}", fen);
+        assert!(
+            legal
+                .iter()
+                .all(|mv| noisy.contains(mv) || quiet.contains(mv)),
+            "{}",
+            fen
+        );
+        assert!(noisy.iter().all(|&mv| board.is_noisy(mv)), "{}", fen);
+
+        let quiet_checks = board.gen_moves(GenType::QuietChecks);
+        let gives_check = |&mv: &ChessMove| {
+            let mut child = board.clone();
+            child.make_move(mv);
+            child.in_check()
+        };
+        let expected: Vec<_> = quiet.iter().copied().filter(gives_check).collect();
+        assert_eq!(quiet_checks[..], expected[..], "{}", fen);
+
+        if board.in_check() {
+            assert_eq!(board.gen_moves(GenType::Evasions)[..], legal[..], "{}", fen);
+        }
+
+        if depth > 1 {
+            for mv in legal {
+                board.make_move(mv);
+                check_gen_types(board, depth - 1);
+                board.unmake_move(mv);
+            }
+        }
+    }
+
+    #[test]
+    fn gen_types_partition_moves() {
+        for fen in [
+            STARTPOS,
+            KIWIPETE,
+            POSITION_3,
+            POSITION_4,
+            POSITION_4_MIRRORED,
+            POSITION_5,
+            POSITION_6,
+        ] {
+            let mut board = ChessBoard::from_fen(fen).unwrap();
+            check_gen_types(&mut board, 3);
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn chess960_deep() {
+        for (fen, counts) in CHESS960 {
+            check(fen, &counts);
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn startpos_deep() {
+        check(STARTPOS, &[20, 400, 8902, 197281, 4865609, 119060324]);
+    }
+
+    #[test]
+    #[ignore]
+    fn kiwipete_deep() {
+        check(KIWIPETE, &[48, 2039, 97862, 4085603, 193690690]);
+    }
+
+    #[test]
+    #[ignore]
+    fn position_3_deep() {
+        check(
+            POSITION_3,
+            &[14, 191, 2812, 43238, 674624, 11030083, 178633661],
+        );
+    }
+
+    #[test]
+    #[ignore]
+    fn position_4_deep() {
+        check(POSITION_4, &[6, 264, 9467, 422333, 15833292]);
+        check(POSITION_4_MIRRORED, &[6, 264, 9467, 422333, 15833292]);
+    }
+
+    #[test]
+    #[ignore]
+    fn position_5_deep() {
+        check(POSITION_5, &[44, 1486, 62379, 2103487, 89941194]);
+    }
+
+    #[test]
+    #[ignore]
+    fn position_6_deep() {
+        check(POSITION_6, &[46, 2079, 89890, 3894594, 164075551]);
+    }
+}
@@ -0,0 +1,744 @@
+//! Finding the best move in a position with an iterative deepening alpha-beta search. Each
+//! iteration searches one ply deeper than the last, which lets the search stop at any time with
+//! the result of the last iteration it finished.
+
+use super::board::ChessBoard;
+use super::history::{History, PieceTo};
+use super::movegen::{ChessMove, GenType};
+use super::movepick::{material_gain, mvv_lva, MovePicker};
+use super::tt::{Bound, TranspositionTable};
+use crate::movelist::MoveList;
+use crate::types::*;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// The deepest the search can go, in plies from the root
+pub const MAX_PLY: usize = 128;
+
+/// The score for delivering checkmate straight away. A mate found further from the root scores
+/// one less for each ply, so that the search prefers the quickest mate.
+pub const MATE: i32 = 32000;
+
+/// A score higher than any real one, used as the initial bounds of the search
+const INFINITY: i32 = MATE + 1;
+
+/// Scores at least this far from zero are mates
+pub(super) const MATE_BOUND: i32 = MATE - MAX_PLY as i32;
+
+/// How much a capture in the quiescence search may gain on top of the captured piece, for the
+/// sake of delta pruning. This allows for the positional gains the material count can't see.
+const DELTA_MARGIN: i32 = 200;
+
+/// How many nodes are searched between checks of the clock and the stop flag
+const CHECK_INTERVAL: u64 = 1024;
+
+/// The score of a position as reported to the user
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Score {
+    /// An advantage in centipawns for the side to move
+    Cp(i32),
+    /// A forced mate in the given number of moves, which is negative if the side to move is the
+    /// one getting mated
+    Mate(i32),
+}
+
+impl From<i32> for Score {
+    /// Convert a score from the search, which counts mates in plies from the root
+    fn from(score: i32) -> Score {
+        if score >= MATE_BOUND {
+            Score::Mate((MATE - score + 1) / 2)
+        } else if score <= -MATE_BOUND {
+            Score::Mate(-(MATE + score) / 2)
+        } else {
+            Score::Cp(score)
+        }
+    }
+}
+
+/// Written as in the UCI protocol, such as "cp 35" or "mate -2"
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Score::Cp(cp) => write!(f, "cp {}", cp),
+            Score::Mate(moves) => write!(f, "mate {}", moves),
+        }
+    }
+}
+
+/// When to stop searching. The search stops as soon as any limit is reached, and only stops when
+/// told to if there are no limits at all.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SearchLimits {
+    /// The deepest iteration to search, in plies
+    pub depth: Option<u32>,
+    /// The most nodes to search
+    pub nodes: Option<u64>,
+    /// How long to search for
+    pub movetime: Option<Duration>,
+}
+
+/// The result of an iteration of the search
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SearchInfo {
+    /// The depth of the iteration, or zero if the search stopped before the first one finished
+    pub depth: u32,
+    pub score: Score,
+    /// The nodes searched so far, over every iteration
+    pub nodes: u64,
+    /// The time spent so far, over every iteration
+    pub time: Duration,
+    /// The principal variation, which is the line both sides are expected to play
+    pub pv: Vec<ChessMove>,
+    /// How full the transposition table is, in permille
+    pub hashfull: usize,
+}
+
+impl SearchInfo {
+    /// The move the search thinks is best, which is None if there are no legal moves
+    pub fn best_move(&self) -> Option<ChessMove> {
+        self.pv.first().copied()
+    }
+
+    /// Nodes searched per second
+    pub fn nps(&self) -> u64 {
+        let secs = self.time.as_secs_f64();
+        if secs > 0.0 {
+            (self.nodes as f64 / secs) as u64
+        } else {
+            0
+        }
+    }
+}
+
+/// The best line found from each ply, filled in as the search returns. The line at a ply is its
+/// best move followed by the line of the ply below.
+struct PvTable {
+    moves: [[ChessMove; MAX_PLY]; MAX_PLY],
+    len: [usize; MAX_PLY],
+}
+
+impl PvTable {
+    fn update(&mut self, ply: usize, mv: ChessMove) {
+        let child_len = if ply + 1 < MAX_PLY {
+            self.len[ply + 1]
+        } else {
+            0
+        };
+        let (parent, child) = self.moves.split_at_mut(ply + 1);
+        parent[ply][0] = mv;
+        if child_len > 0 {
+            parent[ply][1..=child_len].copy_from_slice(&child[0][..child_len]);
+        }
+        self.len[ply] = child_len + 1;
+    }
+
+    fn line(&self, ply: usize) -> &[ChessMove] {
+        &self.moves[ply][..self.len[ply]]
+    }
+}
+
+/// Runs searches, keeping its state between them
+pub struct Searcher {
+    tt: Arc<TranspositionTable>,
+    stop: Arc<AtomicBool>,
+    limits: SearchLimits,
+    start: Instant,
+    nodes: u64,
+    // Set once a limit is reached or the stop flag is seen, after which the search unwinds
+    stopped: bool,
+    // The best root move found so far and its score, or the first root move started with a
+    // static score, for when the first iteration is cut short
+    root_best: Option<(ChessMove, i32)>,
+    pv: Box<PvTable>,
+    history: History,
+    // The piece and destination of the move made at each ply, for the countermove and
+    // continuation history of the ply below
+    moved: [Option<PieceTo>; MAX_PLY],
+}
+
+impl Default for Searcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Searcher {
+    /// Create a searcher with a transposition table of the default size
+    pub fn new() -> Self {
+        Self::with_tt(Arc::new(TranspositionTable::default()))
+    }
+
+    /// Create a searcher using a transposition table, which can be shared with searchers on
+    /// other threads
+    pub fn with_tt(tt: Arc<TranspositionTable>) -> Self {
+        Searcher {
+            tt,
+            stop: Arc::new(AtomicBool::new(false)),
+            limits: SearchLimits::default(),
+            start: Instant::now(),
+            nodes: 0,
+            stopped: false,
+            root_best: None,
+            pv: Box::new(PvTable {
+                moves: [[ChessMove::default(); MAX_PLY]; MAX_PLY],
+                len: [0; MAX_PLY],
+            }),
+            history: History::new(),
+            moved: [None; MAX_PLY],
+        }
+    }
+
+    /// The transposition table the searcher uses
+    pub fn tt(&self) -> &Arc<TranspositionTable> {
+        &self.tt
+    }
+
+    /// Replace the transposition table with an empty one of the given size in megabytes
+    pub fn set_hash_size(&mut self, mb: usize) {
+        self.tt = Arc::new(TranspositionTable::new(mb));
+    }
+
+    /// A flag which stops the search when set, for example from another thread while the search
+    /// is running. The search never clears it, since a stop sent just before the search thread
+    /// gets going would be lost, so call `reset_stop` before starting each search.
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.stop)
+    }
+
+    /// Clear the stop flag, ready for the next search. This should happen on the thread which
+    /// sets the flag, before the search is started, so that it can't undo a stop meant for the
+    /// new search.
+    pub fn reset_stop(&self) {
+        self.stop.store(false, Ordering::Relaxed);
+    }
+
+    /// Get ready for a new search: clear the stop flag and start a new generation in the
+    /// transposition table. Call this once before each search. When several searchers share a
+    /// table, only one of them should call this, and the others only `reset_stop`, so that the
+    /// table ages by one generation per search.
+    pub fn new_search(&self) {
+        self.reset_stop();
+        self.tt.new_search();
+    }
+
+    /// Search the position until a limit is reached or the stop flag is set, calling `report`
+    /// after each iteration that finishes. Returns the result of the last finished iteration.
+    /// A search stopped before the first iteration finishes returns what it found so far, so
+    /// there is still a move to play. Call `new_search` first.
+    pub fn search(
+        &mut self,
+        board: &mut ChessBoard,
+        limits: SearchLimits,
+        mut report: impl FnMut(&SearchInfo),
+    ) -> SearchInfo {
+        self.limits = limits;
+        self.start = Instant::now();
+        self.nodes = 0;
+        self.stopped = false;
+        self.root_best = None;
+        self.history.age();
+
+        let max_depth = limits
+            .depth
+            .unwrap_or(MAX_PLY as u32)
+            .clamp(1, MAX_PLY as u32 - 1);
+        let mut result = None;
+        for depth in 1..=max_depth {
+            // The flag is only checked now and then inside an iteration, so check it here too
+            if depth > 1 && self.stop.load(Ordering::Relaxed) {
+                break;
+            }
+            let score = self.negamax(board, depth, 0, -INFINITY, INFINITY);
+            if self.stopped {
+                if result.is_none() {
+                    result = Some(self.unfinished_result(board));
+                }
+                break;
+            }
+
+            let info = SearchInfo {
+                depth,
+                score: Score::from(score),
+                nodes: self.nodes,
+                time: self.start.elapsed(),
+                pv: self.pv.line(0).to_vec(),
+                hashfull: self.tt.hashfull(),
+            };
+            report(&info);
+            // Searching deeper won't find anything when there are no moves at all
+            let no_moves = info.pv.is_empty();
+            result = Some(info);
+            if no_moves {
+                break;
+            }
+        }
+        result.expect("a stopped search falls back on an unfinished result")
+    }
+
+    /// The result of a search stopped before its first iteration finished, which is the best
+    /// root move found so far. Failing that, it is the first root move started, or any legal
+    /// move if not even that, so that there is a move to play whenever the position has one.
+    fn unfinished_result(&self, board: &ChessBoard) -> SearchInfo {
+        let (mv, score) = match self.root_best {
+            Some((mv, score)) => (Some(mv), score),
+            None => (board.gen_legal().first().copied(), board.evaluate()),
+        };
+        SearchInfo {
+            depth: 0,
+            score: Score::from(score),
+            nodes: self.nodes,
+            time: self.start.elapsed(),
+            pv: mv.into_iter().collect(),
+            hashfull: self.tt.hashfull(),
+        }
+    }
+
+    /// Return true if the search should stop, checking the limits and the stop flag
+    fn should_stop(&mut self) -> bool {
+        if self.stopped {
+            return true;
+        }
+        if self.limits.nodes.is_some_and(|nodes| self.nodes >= nodes)
+            || (self.nodes.is_multiple_of(CHECK_INTERVAL)
+                && (self.stop.load(Ordering::Relaxed)
+                    || self
+                        .limits
+                        .movetime
+                        .is_some_and(|movetime| self.start.elapsed() >= movetime)))
+        {
+            self.stopped = true;
+        }
+        self.stopped
+    }
+
+    /// Search a position to a depth, returning its score for the side to move. Scores outside
+    /// the window from `alpha` to `beta` are only bounds on the true score.
+    fn negamax(
+        &mut self,
+        board: &mut ChessBoard,
+        depth: u32,
+        ply: usize,
+        mut alpha: i32,
+        beta: i32,
+    ) -> i32 {
+        if depth == 0 {
+            return self.qsearch(board, ply, alpha, beta);
+        }
+
+        self.pv.len[ply] = 0;
+        self.nodes += 1;
+        if self.should_stop() {
+            return 0;
+        }
+        if ply > 0 && is_draw(board) {
+            return 0;
+        }
+        if ply >= MAX_PLY - 1 {
+            return board.evaluate();
+        }
+
+        // A stored result from at least as deep a search can stand in for searching again, as
+        // long as it falls outside the window. A score inside the window would need a principal
+        // variation to go with it.
+        let entry = self.tt.probe(board.hash(), ply);
+        if let Some(entry) = entry.filter(|entry| ply > 0 && entry.depth >= depth) {
+            let cutoff = match entry.bound {
+                Bound::Lower => entry.score >= beta,
+                Bound::Upper => entry.score <= alpha,
+                Bound::Exact => entry.score >= beta || entry.score <= alpha,
+            };
+            if cutoff {
+                return entry.score;
+            }
+        }
+        let tt_move = entry.and_then(|entry| entry.mv);
+
+        let prev = ply.checked_sub(1).and_then(|ply| self.moved[ply]);
+        let mut picker = MovePicker::new(
+            tt_move,
+            self.history.killers(ply),
+            self.history.countermove(prev),
+            prev,
+        );
+        // The quiet moves tried so far, whose history scores go down if a later move cuts off
+        let mut quiets = MoveList::<ChessMove>::new();
+
+        let original_alpha = alpha;
+        let mut best_score = -INFINITY;
+        let mut best_move = None;
+        let mut move_count = 0;
+        while let Some(mv) = picker.next(board, &self.history) {
+            move_count += 1;
+            let quiet = !board.is_noisy(mv);
+            if quiet {
+                quiets.push(mv);
+            }
+
+            if ply == 0 && self.root_best.is_none() {
+                self.root_best = Some((mv, board.evaluate()));
+            }
+
+            self.moved[ply] = Some(PieceTo::new(board, mv));
+            board.make_move(mv);
+            self.tt.prefetch(board.hash());
+            let score = -self.negamax(board, depth - 1, ply + 1, -beta, -alpha);
+            board.unmake_move(mv);
+            if self.stopped {
+                return 0;
+            }
+
+            if score > best_score {
+                best_score = score;
+                best_move = Some(mv);
+                if score > alpha {
+                    alpha = score;
+                    self.pv.update(ply, mv);
+                    if ply == 0 {
+                        self.root_best = Some((mv, score));
+                    }
+                    if alpha >= beta {
+                        if quiet {
+                            self.history.update(board, mv, &quiets, prev, ply, depth);
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+
+        if move_count == 0 {
+            return if board.in_check() {
+                -MATE + ply as i32
+            } else {
+                0
+            };
+        }
+
+        let bound = if best_score >= beta {
+            Bound::Lower
+        } else if best_score > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        self.tt
+            .store(board.hash(), best_move, best_score, depth, bound, ply);
+        best_score
+    }
+
+    /// Search only captures and queen promotions until the position is quiet, so that the score
+    /// of a leaf doesn't depend on an exchange which is still going on. The side to move may
+    /// stand pat on the static evaluation instead of capturing, since it usually has a quiet move
+    /// at least that good. When in check every evasion is searched, since standing pat isn't
+    /// possible.
+    fn qsearch(&mut self, board: &mut ChessBoard, ply: usize, mut alpha: i32, beta: i32) -> i32 {
+        self.pv.len[ply] = 0;
+        self.nodes += 1;
+        if self.should_stop() {
+            return 0;
+        }
+        if ply > 0 && is_draw(board) {
+            return 0;
+        }
+        if ply >= MAX_PLY - 1 {
+            return board.evaluate();
+        }
+
+        let in_check = board.in_check();
+        let (mut moves, stand_pat) = if in_check {
+            let evasions = board.gen_moves(GenType::Evasions);
+            if evasions.is_empty() {
+                return -MATE + ply as i32;
+            }
+            (evasions, -INFINITY)
+        } else {
+            let stand_pat = board.evaluate();
+            if stand_pat >= beta {
+                return stand_pat;
+            }
+            (board.gen_noisy(), stand_pat)
+        };
+        alpha = alpha.max(stand_pat);
+
+        for i in 0..moves.len() {
+            let score = mvv_lva(board, moves[i]);
+            moves.set_score(i, score);
+        }
+
+        let mut best_score = stand_pat;
+        let mut index = 0;
+        while let Some(mv) = moves.pick_best(index) {
+            index += 1;
+
+            if !in_check {
+                // Delta pruning skips captures which can't raise alpha even if the capturing
+                // piece is never taken back
+                if stand_pat + material_gain(board, mv) + DELTA_MARGIN <= alpha {
+                    continue;
+                }
+                // Captures which lose material are very unlikely to be any good
+                if !board.see_ge(mv, 0) {
+                    continue;
+                }
+            }
+
+            board.make_move(mv);
+            let score = -self.qsearch(board, ply + 1, -beta, -alpha);
+            board.unmake_move(mv);
+            if self.stopped {
+                return 0;
+            }
+
+            if score > best_score {
+                best_score = score;
+                if score > alpha {
+                    alpha = score;
+                    self.pv.update(ply, mv);
+                    if alpha >= beta {
+                        break;
+                    }
+                }
+            }
+        }
+        best_score
+    }
+}
+
+/// Return true if the position is a draw by repetition, the fifty-move rule or insufficient
+/// material. A position seen before in the game or the search is treated as a draw, since
+/// repeating it is always possible. Checkmate on the hundredth halfmove still wins, like in
+/// `ChessBoard::status`, so the moves are only generated when the side to move is in check there.
+fn is_draw(board: &ChessBoard) -> bool {
+    board.is_repetition(2)
+        || board.is_insufficient_material()
+        || (board.is_fifty_move_draw() && !(board.in_check() && board.gen_legal().is_empty()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn search(fen: &str, limits: SearchLimits) -> SearchInfo {
+        let mut board = ChessBoard::from_fen(fen).unwrap();
+        Searcher::new().search(&mut board, limits, |_| {})
+    }
+
+    fn depth(depth: u32) -> SearchLimits {
+        SearchLimits {
+            depth: Some(depth),
+            ..SearchLimits::default()
+        }
+    }
+
+    #[test]
+    fn mate_scores() {
+        assert_eq!(Score::from(MATE - 1), Score::Mate(1));
+        assert_eq!(Score::from(MATE - 3), Score::Mate(2));
+        assert_eq!(Score::from(-MATE + 2), Score::Mate(-1));
+        assert_eq!(Score::from(-MATE + 4), Score::Mate(-2));
+        assert_eq!(Score::from(150), Score::Cp(150));
+        assert_eq!(Score::Mate(-2).to_string(), "mate -2");
+        assert_eq!(Score::Cp(35).to_string(), "cp 35");
+    }
+
+    #[test]
+    fn finds_mate_in_one() {
+        // Ra8 is a back rank mate
+        let info = search("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", depth(3));
+        assert_eq!(info.best_move(), Some(ChessMove::new(0, 56, 0, 0)));
+        assert_eq!(info.score, Score::Mate(1));
+    }
+
+    #[test]
+    fn mate_beats_fifty_move_rule() {
+        // Ra8 is mate on the hundredth halfmove, which leaves no time to claim the draw
+        let info = search("6k1/5ppp/8/8/8/8/8/R5K1 w - - 99 1", depth(3));
+        assert_eq!(info.best_move(), Some(ChessMove::new(0, 56, 0, 0)));
+        assert_eq!(info.score, Score::Mate(1));
+    }
+
+    #[test]
+    fn sees_mate_coming() {
+        // White can only push the pawn, after which the queen mates on g2
+        let info = search("8/8/8/8/8/5kq1/P7/7K w - - 0 1", depth(3));
+        assert_eq!(info.score, Score::Mate(-1));
+        assert_eq!(info.pv.len(), 2);
+    }
+
+    #[test]
+    fn wins_material() {
+        // The queen on d5 is hanging
+        let info = search("4k3/8/8/3q4/8/8/8/3RK3 w - - 0 1", depth(2));
+        assert_eq!(info.best_move(), Some(ChessMove::new(3, 35, 0, 0)));
+        // Up a rook, give or take where the pieces stand
+        assert!(
+            matches!(info.score, Score::Cp(400..=600)),
+            "{:?}",
+            info.score
+        );
+    }
+
+    #[test]
+    fn quiescence_sees_recaptures() {
+        // Taking the pawn on d5 loses the queen to the pawn on c6, which a one ply search can
+        // only see by following the captures to the end
+        let info = search("4k3/8/2p5/3p4/8/8/8/3QK3 w - - 0 1", depth(1));
+        assert_ne!(info.best_move(), Some(ChessMove::new(3, 35, 0, 0)));
+        // Up a queen for two pawns
+        assert!(
+            matches!(info.score, Score::Cp(600..=900)),
+            "{:?}",
+            info.score
+        );
+    }
+
+    #[test]
+    fn quiescence_searches_evasions() {
+        // After Ra8 black is in check at a leaf, and has no evasions
+        let info = search("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", depth(1));
+        assert_eq!(info.best_move(), Some(ChessMove::new(0, 56, 0, 0)));
+        assert_eq!(info.score, Score::Mate(1));
+    }
+
+    #[test]
+    fn quiescence_mate_beats_fifty_move_rule() {
+        // After Ra8 on the hundredth halfmove, black is mated at a leaf rather than drawn
+        let info = search("6k1/5ppp/8/8/8/8/8/R5K1 w - - 99 1", depth(1));
+        assert_eq!(info.best_move(), Some(ChessMove::new(0, 56, 0, 0)));
+        assert_eq!(info.score, Score::Mate(1));
+    }
+
+    #[test]
+    fn no_legal_moves() {
+        let info = search("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", depth(3));
+        assert_eq!(info.best_move(), None);
+        assert_eq!(info.score, Score::Cp(0));
+        assert_eq!(info.depth, 1);
+    }
+
+    #[test]
+    fn reports_each_iteration() {
+        let mut board = ChessBoard::from_fen(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        )
+        .unwrap();
+        let mut depths = Vec::new();
+        let info = Searcher::new().search(&mut board, depth(3), |info| {
+            assert!(!info.pv.is_empty());
+            depths.push(info.depth)
+        });
+
+        assert_eq!(depths, vec![1, 2, 3]);
+        assert_eq!(info.depth, 3);
+        // The board is left as it was
+        assert_eq!(
+            board.to_fen(),
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1"
+        );
+    }
+
+    #[test]
+    fn reuses_transposition_table() {
+        let mut board = ChessBoard::from_fen(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        )
+        .unwrap();
+        let mut searcher = Searcher::with_tt(Arc::new(TranspositionTable::new(1)));
+        searcher.new_search();
+        let first = searcher.search(&mut board, depth(4), |_| {});
+        searcher.new_search();
+        let second = searcher.search(&mut board, depth(4), |_| {});
+
+        assert!(first.hashfull > 0);
+        // The second search finds the results of the first in the table
+        assert!(second.nodes < first.nodes / 2);
+        assert_eq!(second.score, first.score);
+        assert_eq!(second.best_move(), first.best_move());
+    }
+
+    #[test]
+    fn node_limit() {
+        let limits = SearchLimits {
+            nodes: Some(5000),
+            ..SearchLimits::default()
+        };
+        let info = search(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            limits,
+        );
+        assert!(info.nodes <= 5000);
+        assert!(info.best_move().is_some());
+    }
+
+    #[test]
+    fn node_limit_in_first_iteration() {
+        // There are 48 moves here, so a handful of nodes stops the first iteration part way
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let board = ChessBoard::from_fen(fen).unwrap();
+        for nodes in [1, 2, 20] {
+            let limits = SearchLimits {
+                nodes: Some(nodes),
+                ..SearchLimits::default()
+            };
+            let info = search(fen, limits);
+            assert!(info.nodes <= nodes, "{} > {}", info.nodes, nodes);
+            assert_eq!(info.depth, 0);
+            assert!(board.gen_legal().contains(&info.best_move().unwrap()));
+        }
+    }
+
+    #[test]
+    fn stop_from_another_thread() {
+        let mut searcher = Searcher::new();
+        let stop = searcher.stop_flag();
+        searcher.new_search();
+        let handle = thread::spawn(move || {
+            let mut board =
+                ChessBoard::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")
+                    .unwrap();
+            searcher.search(&mut board, SearchLimits::default(), |_| {})
+        });
+
+        thread::sleep(Duration::from_millis(100));
+        stop.store(true, Ordering::Relaxed);
+        let info = handle.join().unwrap();
+        assert!(info.best_move().is_some());
+        assert!(info.depth < MAX_PLY as u32 - 1);
+    }
+
+    #[test]
+    fn stop_before_search_starts() {
+        let mut searcher = Searcher::new();
+        let stop = searcher.stop_flag();
+        searcher.new_search();
+        stop.store(true, Ordering::Relaxed);
+        let handle = thread::spawn(move || {
+            let mut board =
+                ChessBoard::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")
+                    .unwrap();
+            searcher.search(&mut board, SearchLimits::default(), |_| {})
+        });
+
+        let info = handle.join().unwrap();
+        assert!(info.best_move().is_some());
+        assert!(info.depth <= 1);
+    }
+
+    #[test]
+    fn movetime_limit() {
+        let limits = SearchLimits {
+            movetime: Some(Duration::from_millis(50)),
+            ..SearchLimits::default()
+        };
+        let start = Instant::now();
+        let info = search(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            limits,
+        );
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert!(info.best_move().is_some());
+    }
+}
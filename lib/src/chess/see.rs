@@ -0,0 +1,223 @@
+//! Static exchange evaluation works out what a move wins or loses in material if both sides keep
+//! capturing on the square it moves to, always with their least valuable piece first. It is far
+//! cheaper than searching the captures, which makes it useful for ordering and pruning them.
+
+use super::attacks::*;
+use super::board::{en_passant_capture_square, ChessBoard};
+use super::eval::PIECE_VALUES;
+use super::movegen::ChessMove;
+use super::types::*;
+use crate::bitboards::Bitboard;
+
+/// The value of each piece type for static exchange evaluation, in centipawns. These are the
+/// usual piece values, except that the king is worth far more than everything else, since
+/// capturing it ends the game.
+pub const SEE_VALUES: [i32; PIECE_TYPE_COUNT] = {
+    let mut values = PIECE_VALUES;
+    values[PieceType::King as usize] = 20000;
+    values
+};
+
+#[inline]
+fn see_value(pt: PieceType) -> i32 {
+    SEE_VALUES[pt as usize]
+}
+
+impl ChessBoard {
+    /// The material the side to move gains from a legal move once every worthwhile capture on
+    /// the destination square has been played out. A negative value means the move loses
+    /// material. Pins are not taken into account.
+    pub fn see(&self, mv: ChessMove) -> i32 {
+        if self.is_castling(mv) {
+            return 0;
+        }
+
+        let (from, to) = (mv.from().index(), mv.to().index());
+        let promotion_gain = see_value(PieceType::Queen) - see_value(PieceType::Pawn);
+
+        // The material gained after each capture in the sequence, from the point of view of the
+        // side making that capture
+        let mut gain = [0; 32];
+        let mut occ = self.occ();
+        occ.clear_bit(from);
+        if mv.is_en_passant() {
+            occ.clear_bit(en_passant_capture_square(to, self.turn));
+        }
+        // The value of the piece standing on the square, which the next capture wins
+        let mut on_square;
+        (gain[0], on_square) = self.first_capture(mv);
+
+        let diagonal = |pt| {
+            self.piece_bb[Piece::from((pt, ChessSide::White))]
+                | self.piece_bb[Piece::from((pt, ChessSide::Black))]
+        };
+        let bishops = diagonal(PieceType::Bishop) | diagonal(PieceType::Queen);
+        let rooks = diagonal(PieceType::Rook) | diagonal(PieceType::Queen);
+
+        let mut attackers = self.attackers_to(mv.to(), occ) & occ;
+        let mut side = !self.turn;
+        let mut depth = 0;
+        loop {
+            let ours = attackers & self.side_bb[side];
+            let Some((pt, sq)) = self.least_valuable(ours, side) else {
+                break;
+            };
+            // The king may only capture when nothing can take it back
+            if pt == PieceType::King && !(attackers & self.side_bb[!side]).is_empty() {
+                break;
+            }
+
+            depth += 1;
+            // A pawn can only capture onto the first or last rank by promoting
+            let promotes = pt == PieceType::Pawn && (to / 8 == 7 || to / 8 == 0);
+            gain[depth] = on_square + if promotes { promotion_gain } else { 0 } - gain[depth - 1];
+            on_square = if promotes {
+                see_value(PieceType::Queen)
+            } else {
+                see_value(pt)
+            };
+
+            // Removing the capturing piece may uncover a slider behind it
+            occ.clear_bit(sq);
+            attackers = (attackers
+                | (get_bishop_attacks(to, occ) & bishops)
+                | (get_rook_attacks(to, occ) & rooks))
+                & occ;
+            side = !side;
+        }
+
+        // Each side only continues the sequence if doing so is better than stopping
+        while depth > 0 {
+            gain[depth - 1] = -(-gain[depth - 1]).max(gain[depth]);
+            depth -= 1;
+        }
+        gain[0]
+    }
+
+    /// Return true if the static exchange evaluation of a move is at least the threshold. This
+    /// is often decided by the move alone, without working through the exchange.
+    pub fn see_ge(&self, mv: ChessMove, threshold: i32) -> bool {
+        if self.is_castling(mv) {
+            return 0 >= threshold;
+        }
+
+        // The exchange can never win more than the move itself does, and can't lose more than
+        // the moved piece since we can always stop recapturing. On the last rank a recapturing
+        // pawn promotes as well.
+        let (gain, moved) = self.first_capture(mv);
+        let mut worst = gain - moved;
+        if mv.to().rank() == 0 || mv.to().rank() == 7 {
+            worst -= see_value(PieceType::Queen) - see_value(PieceType::Pawn);
+        }
+        if gain < threshold {
+            false
+        } else if worst >= threshold {
+            true
+        } else {
+            self.see(mv) >= threshold
+        }
+    }
+
+    /// The material won by a move before any recapture, and the value of the piece it leaves on
+    /// the destination square
+    fn first_capture(&self, mv: ChessMove) -> (i32, i32) {
+        let captured = if mv.is_en_passant() {
+            see_value(PieceType::Pawn)
+        } else if self.is_capture(mv) {
+            see_value(PieceType::from(self.piece_on(mv.to())))
+        } else {
+            0
+        };
+        match mv.promotion() {
+            Some(promotion) => (
+                captured + see_value(promotion) - see_value(PieceType::Pawn),
+                see_value(promotion),
+            ),
+            None => (
+                captured,
+                see_value(PieceType::from(self.piece_on(mv.from()))),
+            ),
+        }
+    }
+
+    /// The least valuable piece of a side among a set of pieces, along with its square
+    fn least_valuable(&self, pieces: Bitboard, side: ChessSide) -> Option<(PieceType, u8)> {
+        [
+            PieceType::Pawn,
+            PieceType::Knight,
+            PieceType::Bishop,
+            PieceType::Rook,
+            PieceType::Queen,
+            PieceType::King,
+        ]
+        .into_iter()
+        .find_map(|pt| {
+            let bb = pieces & self.piece_bb[Piece::from((pt, side))];
+            (!bb.is_empty()).then(|| (pt, bb.lsb() as u8))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::*;
+
+    // Each line is a position, a move in UCI notation and the expected exchange value, with
+    // pawns worth 100, knights and bishops 325, rooks 500 and queens 1000
+    const SEE_SUITE: &[&str] = &[
+        "4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1 | e4d5 | 100",
+        "4k3/8/2p5/3p4/4P3/8/8/4K3 w - - 0 1 | e4d5 | 0",
+        "4k3/8/2p5/3p4/8/8/8/3QK3 w - - 0 1 | d1d5 | -900",
+        "3rk3/8/8/3p4/8/8/3R4/3RK3 w - - 0 1 | d2d5 | 100",
+        "4k3/8/4p3/3p4/8/5B2/6Q1/4K3 w - - 0 1 | f3d5 | -125",
+        "4k3/8/4p3/3p4/8/5B2/8/4K3 w - - 0 1 | f3d5 | -225",
+        "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1 | e5d6 | 100",
+        "4k3/2p5/8/3pP3/8/8/8/4K3 w - d6 0 1 | e5d6 | 0",
+        "4k3/1P6/8/8/8/8/8/4K3 w - - 0 1 | b7b8q | 900",
+        "r3k3/1P6/8/8/8/8/8/4K3 w - - 0 1 | b7b8q | -100",
+        "r3k3/1P6/8/8/8/8/8/4K3 w - - 0 1 | b7a8q | 1400",
+        "r3k3/1P6/8/8/8/8/8/4K3 w - - 0 1 | b7b8n | -100",
+        "3R2k1/2P5/8/8/8/8/7K/3q4 b - - 0 1 | d1d8 | -1400",
+        "8/8/2k5/3p4/8/8/8/3RK3 w - - 0 1 | d1d5 | -400",
+        "8/8/2k5/3p4/8/5B2/8/3RK3 w - - 0 1 | d1d5 | 100",
+        "4k3/8/8/8/2p5/8/8/4KB2 w - - 0 1 | f1d3 | -325",
+        "r3k3/8/8/8/8/8/8/4K2R w K - 0 1 | e1g1 | 0",
+        "4R3/2r3p1/5bk1/1p1r3p/p2PR1P1/P1BK1P2/1P6/8 b - - 0 1 | h5g4 | 0",
+        "4R3/2r3p1/5bk1/1p1r1p1p/p2PR1P1/P1BK1P2/1P6/8 b - - 0 1 | h5g4 | 0",
+        "2r1r1k1/pp1bppbp/3p1np1/q3P3/2P2P2/1P2B3/P1N1B1PP/2RQ1RK1 b - - 0 1 | d6e5 | 100",
+    ];
+
+    #[test]
+    fn see_suite() {
+        for line in SEE_SUITE {
+            let fields: Vec<_> = line.split(" | ").collect();
+            let board = ChessBoard::from_fen(fields[0]).unwrap();
+            let mv = board.parse_uci_move(fields[1]).unwrap();
+            let value: i32 = fields[2].parse().unwrap();
+
+            assert_eq!(board.see(mv), value, "{}", line);
+            assert!(board.see_ge(mv, value), "{}", line);
+            assert!(!board.see_ge(mv, value + 1), "{}", line);
+        }
+    }
+
+    #[test]
+    fn see_ge_matches_see() {
+        for line in SEE_SUITE {
+            let board = ChessBoard::from_fen(line.split(" | ").next().unwrap()).unwrap();
+            for mv in board.gen_legal() {
+                let value = board.see(mv);
+                for threshold in (-1500..=1500).step_by(25) {
+                    assert_eq!(
+                        board.see_ge(mv, threshold),
+                        value >= threshold,
+                        "{} {}",
+                        line,
+                        mv
+                    );
+                }
+            }
+        }
+    }
+}
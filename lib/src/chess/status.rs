@@ -0,0 +1,178 @@
+//! Working out whether a game has ended, and how
+
+use super::board::ChessBoard;
+use super::types::ChessSide;
+use crate::types::*;
+use std::fmt;
+
+/// Why a game was drawn
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DrawReason {
+    InsufficientMaterial,
+    FiftyMoveRule,
+    ThreefoldRepetition,
+}
+
+/// The state of the game in a position
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameStatus {
+    Ongoing,
+    /// The side to move has been checkmated by the given side
+    Checkmate(ChessSide),
+    Stalemate,
+    Draw(DrawReason),
+}
+
+/// The result of a game as written in PGN
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+}
+
+impl GameStatus {
+    /// The result of the game, or None if it hasn't finished
+    pub fn result(&self) -> Option<GameResult> {
+        match self {
+            GameStatus::Ongoing => None,
+            GameStatus::Checkmate(ChessSide::White) => Some(GameResult::WhiteWins),
+            GameStatus::Checkmate(ChessSide::Black) => Some(GameResult::BlackWins),
+            GameStatus::Stalemate | GameStatus::Draw(_) => Some(GameResult::Draw),
+        }
+    }
+}
+
+impl fmt::Display for GameResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            GameResult::WhiteWins => "1-0",
+            GameResult::BlackWins => "0-1",
+            GameResult::Draw => "1/2-1/2",
+        })
+    }
+}
+
+impl ChessBoard {
+    /// Work out whether the game is over. Draws which a player would have to claim, by the
+    /// fifty-move rule or threefold repetition, are treated as drawn since that is what match
+    /// runners want. Checkmate takes priority over every draw.
+    pub fn status(&self) -> GameStatus {
+        if self.gen_legal().is_empty() {
+            return if self.in_check() {
+                GameStatus::Checkmate(!self.turn)
+            } else {
+                GameStatus::Stalemate
+            };
+        }
+
+        if self.is_insufficient_material() {
+            GameStatus::Draw(DrawReason::InsufficientMaterial)
+        } else if self.is_fifty_move_draw() {
+            GameStatus::Draw(DrawReason::FiftyMoveRule)
+        } else if self.is_repetition(3) {
+            GameStatus::Draw(DrawReason::ThreefoldRepetition)
+        } else {
+            GameStatus::Ongoing
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chess::ChessMove;
+
+    fn status(fen: &str) -> GameStatus {
+        ChessBoard::from_fen(fen).unwrap().status()
+    }
+
+    #[test]
+    fn check() {
+        let board = ChessBoard::from_fen("4k3/8/8/8/8/3n4/8/4K3 w - - 0 1").unwrap();
+        assert!(board.in_check());
+        assert_eq!(u64::from(board.checkers()), 1 << 19);
+
+        let board = ChessBoard::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert!(!board.in_check());
+        assert!(board.checkers().is_empty());
+    }
+
+    #[test]
+    fn statuses() {
+        assert_eq!(
+            status("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+            GameStatus::Ongoing
+        );
+        // Fool's mate
+        assert_eq!(
+            status("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3"),
+            GameStatus::Checkmate(ChessSide::Black)
+        );
+        assert_eq!(
+            status("7k/6Q1/6K1/8/8/8/8/8 b - - 0 1"),
+            GameStatus::Checkmate(ChessSide::White)
+        );
+        assert_eq!(
+            status("7k/5Q2/8/8/8/8/8/6K1 b - - 0 1"),
+            GameStatus::Stalemate
+        );
+        assert_eq!(
+            status("4k3/8/8/8/8/8/8/4KB2 w - - 0 1"),
+            GameStatus::Draw(DrawReason::InsufficientMaterial)
+        );
+        assert_eq!(
+            status("4k3/8/8/8/8/8/8/4KR2 w - - 100 80"),
+            GameStatus::Draw(DrawReason::FiftyMoveRule)
+        );
+        // Mate on the hundredth halfmove still counts
+        assert_eq!(
+            status("7k/6Q1/6K1/8/8/8/8/8 b - - 100 80"),
+            GameStatus::Checkmate(ChessSide::White)
+        );
+    }
+
+    #[test]
+    fn threefold_repetition() {
+        let mut board = ChessBoard::from_fen("4k3/8/8/8/8/8/8/4KR2 w - - 0 1").unwrap();
+        let shuffle = [
+            ChessMove::new(5, 6, 0, 0),
+            ChessMove::new(60, 59, 0, 0),
+            ChessMove::new(6, 5, 0, 0),
+            ChessMove::new(59, 60, 0, 0),
+        ];
+        for _ in 0..2 {
+            assert_eq!(board.status(), GameStatus::Ongoing);
+            for mv in shuffle {
+                board.make_move(mv);
+            }
+        }
+        assert_eq!(
+            board.status(),
+            GameStatus::Draw(DrawReason::ThreefoldRepetition)
+        );
+    }
+
+    #[test]
+    fn results() {
+        assert_eq!(GameStatus::Ongoing.result(), None);
+        assert_eq!(
+            GameStatus::Checkmate(ChessSide::White)
+                .result()
+                .unwrap()
+                .to_string(),
+            "1-0"
+        );
+        assert_eq!(
+            GameStatus::Checkmate(ChessSide::Black)
+                .result()
+                .unwrap()
+                .to_string(),
+            "0-1"
+        );
+        assert_eq!(
+            GameStatus::Stalemate.result().unwrap().to_string(),
+            "1/2-1/2"
+        );
+    }
+}
@@ -0,0 +1,479 @@
+//! The transposition table remembers what the search found out about positions it has already
+//! seen, keyed by their Zobrist hash. Positions are often reached again through a different move
+//! order, and the result from before can save searching them again or at least give a good move
+//! to try first.
+//!
+//! Each entry is packed into a single atomic word, so threads can share the table without locks
+//! and never see an entry half written by another thread.
+
+use super::movegen::ChessMove;
+use super::search::MATE_BOUND;
+use std::mem;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+/// The size of a new table in megabytes
+pub const DEFAULT_HASH_MB: usize = 16;
+
+/// The entries which a position can be stored in. They share a cache line, so looking through
+/// all of them costs about the same as looking at one.
+const BUCKET_SIZE: usize = 4;
+
+/// How many plies shallower than the entry already stored for a position a new inexact result
+/// may be and still replace it. A slightly shallower result is usually worth more than a deeper
+/// one, since it has the bounds of the current window.
+const REPLACE_DEPTH_MARGIN: u32 = 3;
+
+/// The generation is stored in 6 bits, so it wraps around after this many searches
+const GENERATION_COUNT: u8 = 64;
+
+/// How a stored score relates to the true score of the position
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bound {
+    /// The score is at least as high as the true score, since every move failed low
+    Upper,
+    /// The score is at most as high as the true score, since a move failed high
+    Lower,
+    /// The score is the true score
+    Exact,
+}
+
+/// What the table knows about a position
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TtEntry {
+    /// The best move found, which may not be legal if another position with the same hash
+    /// overwrote the entry
+    pub mv: Option<ChessMove>,
+    pub score: i32,
+    pub depth: u32,
+    pub bound: Bound,
+}
+
+/// An entry unpacked from its word. From the least significant bit, the word holds 16 bits of the
+/// key, the move, the score, the depth, 2 bits for the bound and 6 for the generation. A bound of
+/// zero marks an empty entry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Packed {
+    key: u16,
+    mv: u16,
+    score: i16,
+    depth: u8,
+    bound: u8,
+    generation: u8,
+}
+
+impl Packed {
+    fn unpack(data: u64) -> Packed {
+        Packed {
+            key: data as u16,
+            mv: (data >> 16) as u16,
+            score: (data >> 32) as u16 as i16,
+            depth: (data >> 48) as u8,
+            bound: (data >> 56) as u8 & 0b11,
+            generation: (data >> 58) as u8,
+        }
+    }
+
+    fn pack(self) -> u64 {
+        self.key as u64
+            | (self.mv as u64) << 16
+            | (self.score as u16 as u64) << 32
+            | (self.depth as u64) << 48
+            | (self.bound as u64) << 56
+            | (self.generation as u64) << 58
+    }
+
+    fn is_empty(self) -> bool {
+        self.bound == 0
+    }
+}
+
+#[derive(Default)]
+#[repr(align(32))]
+struct Bucket {
+    entries: [AtomicU64; BUCKET_SIZE],
+}
+
+/// A hash table of search results shared by every thread of a search
+pub struct TranspositionTable {
+    buckets: Vec<Bucket>,
+    generation: AtomicU8,
+}
+
+impl Default for TranspositionTable {
+    fn default() -> Self {
+        Self::new(DEFAULT_HASH_MB)
+    }
+}
+
+impl TranspositionTable {
+    /// Create an empty table taking up about the given number of megabytes
+    pub fn new(mb: usize) -> Self {
+        let count = (mb * 1024 * 1024 / mem::size_of::<Bucket>()).max(1);
+        TranspositionTable {
+            buckets: (0..count).map(|_| Bucket::default()).collect(),
+            generation: AtomicU8::new(0),
+        }
+    }
+
+    /// Change the size of the table, which clears it
+    pub fn resize(&mut self, mb: usize) {
+        *self = Self::new(mb);
+    }
+
+    /// The number of entries the table can hold
+    pub fn capacity(&self) -> usize {
+        self.buckets.len() * BUCKET_SIZE
+    }
+
+    /// Remove every entry
+    pub fn clear(&self) {
+        for bucket in &self.buckets {
+            for entry in &bucket.entries {
+                entry.store(0, Ordering::Relaxed);
+            }
+        }
+        self.generation.store(0, Ordering::Relaxed);
+    }
+
+    /// Start a new search. Entries from earlier searches are replaced before newer ones. This
+    /// should be called once per search, not once by each thread taking part in it.
+    pub fn new_search(&self) {
+        let _ = self
+            .generation
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |generation| {
+                Some((generation + 1) % GENERATION_COUNT)
+            });
+    }
+
+    #[inline]
+    fn bucket(&self, key: u64) -> &Bucket {
+        // The high bits of the key pick the bucket, leaving the low bits to check the entry
+        let index = (key as u128 * self.buckets.len() as u128) >> 64;
+        &self.buckets[index as usize]
+    }
+
+    /// Start loading the bucket of a position into the cache, so that it is ready by the time it
+    /// is probed
+    #[inline]
+    pub fn prefetch(&self, key: u64) {
+        #[cfg(target_arch = "x86_64")]
+        {
+            use std::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+            let ptr = self.bucket(key) as *const Bucket as *const i8;
+            // Safety: prefetching is only a hint which never faults, and SSE is always available
+            // on x86_64
+            #[allow(unused_unsafe)]
+            unsafe {
+                _mm_prefetch::<_MM_HINT_T0>(ptr)
+            };
+        }
+        #[cfg(not(target_arch = "x86_64"))]
+        let _ = key;
+    }
+
+    /// Look up a position, `ply` moves from the root of the search
+    pub fn probe(&self, key: u64, ply: usize) -> Option<TtEntry> {
+        let packed = self
+            .bucket(key)
+            .entries
+            .iter()
+            .map(|entry| Packed::unpack(entry.load(Ordering::Relaxed)))
+            .find(|packed| !packed.is_empty() && packed.key == key as u16)?;
+
+        Some(TtEntry {
+            mv: (packed.mv != 0).then(|| ChessMove::from_bits(packed.mv)),
+            score: score_from_tt(packed.score as i32, ply),
+            depth: packed.depth as u32,
+            bound: match packed.bound {
+                1 => Bound::Upper,
+                2 => Bound::Lower,
+                _ => Bound::Exact,
+            },
+        })
+    }
+
+    /// Store the result of searching a position `ply` moves from the root. An entry for the same
+    /// position is kept instead if it came from a search more than `REPLACE_DEPTH_MARGIN` plies
+    /// deeper in this generation, unless the new score is exact. Otherwise the entry replaced is
+    /// the one which is shallowest and oldest.
+    pub fn store(
+        &self,
+        key: u64,
+        mv: Option<ChessMove>,
+        score: i32,
+        depth: u32,
+        bound: Bound,
+        ply: usize,
+    ) {
+        let generation = self.generation.load(Ordering::Relaxed);
+        let entries = &self.bucket(key).entries;
+        let mut new = Packed {
+            key: key as u16,
+            mv: mv.map_or(0, ChessMove::to_bits),
+            score: score_to_tt(score, ply) as i16,
+            depth: depth.min(u8::MAX as u32) as u8,
+            bound: match bound {
+                Bound::Upper => 1,
+                Bound::Lower => 2,
+                Bound::Exact => 3,
+            },
+            generation,
+        };
+
+        let mut replace = 0;
+        let mut replace_value = i32::MAX;
+        for (i, entry) in entries.iter().enumerate() {
+            let old = Packed::unpack(entry.load(Ordering::Relaxed));
+            if !old.is_empty() && old.key == new.key {
+                if bound != Bound::Exact
+                    && old.generation == generation
+                    && new.depth as u32 + REPLACE_DEPTH_MARGIN < old.depth as u32
+                {
+                    return;
+                }
+                // Keep the move we had if the new result doesn't have one
+                if new.mv == 0 {
+                    new.mv = old.mv;
+                }
+                replace = i;
+                break;
+            }
+
+            let value = if old.is_empty() {
+                i32::MIN
+            } else {
+                let age = (generation + GENERATION_COUNT - old.generation) % GENERATION_COUNT;
+                old.depth as i32 - 8 * age as i32
+            };
+            if value < replace_value {
+                replace = i;
+                replace_value = value;
+            }
+        }
+        entries[replace].store(new.pack(), Ordering::Relaxed);
+    }
+
+    /// How full the table is in permille, estimated from the entries of this search among the
+    /// first thousand
+    pub fn hashfull(&self) -> usize {
+        let generation = self.generation.load(Ordering::Relaxed);
+        let buckets = &self.buckets[..self.buckets.len().min(1000 / BUCKET_SIZE)];
+        let used = buckets
+            .iter()
+            .flat_map(|bucket| &bucket.entries)
+            .map(|entry| Packed::unpack(entry.load(Ordering::Relaxed)))
+            .filter(|packed| !packed.is_empty() && packed.generation == generation)
+            .count();
+        used * 1000 / (buckets.len() * BUCKET_SIZE)
+    }
+}
+
+/// Mate scores in the search count plies from the root, but a stored position can be reached at
+/// a different ply, so they are stored counting from the position instead
+fn score_to_tt(score: i32, ply: usize) -> i32 {
+    if score >= MATE_BOUND {
+        score + ply as i32
+    } else if score <= -MATE_BOUND {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+fn score_from_tt(score: i32, ply: usize) -> i32 {
+    if score >= MATE_BOUND {
+        score - ply as i32
+    } else if score <= -MATE_BOUND {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chess::MATE;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn store_and_probe() {
+        let tt = TranspositionTable::new(1);
+        let mv = ChessMove::new(12, 28, 0, 0);
+        assert_eq!(tt.probe(0x1234_5678_9abc_def0, 0), None);
+
+        tt.store(0x1234_5678_9abc_def0, Some(mv), -250, 7, Bound::Lower, 3);
+        assert_eq!(
+            tt.probe(0x1234_5678_9abc_def0, 3),
+            Some(TtEntry {
+                mv: Some(mv),
+                score: -250,
+                depth: 7,
+                bound: Bound::Lower,
+            })
+        );
+        // A different key in the same bucket doesn't match
+        assert_eq!(tt.probe(0x1234_5678_9abc_def1, 3), None);
+    }
+
+    #[test]
+    fn packing() {
+        let packed = Packed {
+            key: 0xBEEF,
+            mv: ChessMove::new(63, 0, 4, 0).to_bits(),
+            score: -(MATE as i16),
+            depth: 255,
+            bound: 3,
+            generation: 63,
+        };
+        assert_eq!(Packed::unpack(packed.pack()), packed);
+    }
+
+    #[test]
+    fn mate_scores_count_from_the_position() {
+        let tt = TranspositionTable::new(1);
+        // Mate in 3 plies from a position 5 plies deep is mate in 3 plies from wherever it is
+        // found again
+        tt.store(1, None, MATE - 8, 10, Bound::Exact, 5);
+        assert_eq!(tt.probe(1, 2).unwrap().score, MATE - 5);
+
+        tt.store(2, None, -MATE + 8, 10, Bound::Exact, 5);
+        assert_eq!(tt.probe(2, 9).unwrap().score, -MATE + 12);
+
+        tt.store(3, None, 300, 10, Bound::Exact, 5);
+        assert_eq!(tt.probe(3, 9).unwrap().score, 300);
+    }
+
+    #[test]
+    fn replacement() {
+        let tt = TranspositionTable::new(1);
+        let mv = ChessMove::new(1, 18, 0, 0);
+        // Keys which only differ in their low bits share a bucket
+        let key = |i: u64| 0xABCD_0000_0000_0000 | i;
+
+        // A much shallower result for the same position doesn't replace a deeper one from this
+        // search
+        tt.store(key(1), Some(mv), 50, 10, Bound::Lower, 0);
+        tt.store(key(1), None, 20, 2, Bound::Upper, 0);
+        assert_eq!(tt.probe(key(1), 0).unwrap().depth, 10);
+        tt.store(
+            key(1),
+            None,
+            20,
+            10 - REPLACE_DEPTH_MARGIN - 1,
+            Bound::Upper,
+            0,
+        );
+        assert_eq!(tt.probe(key(1), 0).unwrap().depth, 10);
+        // One which is only a little shallower does
+        tt.store(key(1), None, 20, 10 - REPLACE_DEPTH_MARGIN, Bound::Upper, 0);
+        assert_eq!(
+            tt.probe(key(1), 0).unwrap().depth,
+            10 - REPLACE_DEPTH_MARGIN
+        );
+        tt.store(key(1), None, 50, 10, Bound::Lower, 0);
+        tt.store(key(1), None, 20, 9, Bound::Upper, 0);
+        assert_eq!(tt.probe(key(1), 0).unwrap().depth, 9);
+        tt.store(key(1), None, 50, 10, Bound::Lower, 0);
+        // But an exact one does, keeping the move
+        tt.store(key(1), None, 20, 2, Bound::Exact, 0);
+        let entry = tt.probe(key(1), 0).unwrap();
+        assert_eq!((entry.depth, entry.mv), (2, Some(mv)));
+
+        // Once the bucket is full, the shallowest entry is replaced
+        tt.store(key(2), None, 0, 8, Bound::Exact, 0);
+        tt.store(key(3), None, 0, 9, Bound::Exact, 0);
+        tt.store(key(4), None, 0, 7, Bound::Exact, 0);
+        tt.store(key(5), None, 0, 6, Bound::Exact, 0);
+        assert!(tt.probe(key(1), 0).is_none());
+        for i in 2..=5 {
+            assert!(tt.probe(key(i), 0).is_some());
+        }
+
+        // Entries from earlier searches go first, even if they are deeper
+        tt.new_search();
+        tt.store(key(6), None, 0, 1, Bound::Exact, 0);
+        tt.store(key(7), None, 0, 1, Bound::Exact, 0);
+        assert!(tt.probe(key(6), 0).is_some());
+        assert!(tt.probe(key(7), 0).is_some());
+        assert!(tt.probe(key(3), 0).is_some());
+    }
+
+    #[test]
+    fn hashfull_and_clear() {
+        let tt = TranspositionTable::new(1);
+        assert_eq!(tt.hashfull(), 0);
+        for i in 0..tt.capacity() as u64 {
+            // Spread the keys over every bucket
+            tt.store(
+                i.wrapping_mul(0x9E37_79B9_7F4A_7C15),
+                None,
+                0,
+                1,
+                Bound::Exact,
+                0,
+            );
+        }
+        assert!(tt.hashfull() > 500);
+
+        // Entries from an earlier search don't count
+        tt.new_search();
+        assert_eq!(tt.hashfull(), 0);
+
+        tt.clear();
+        assert!(tt.probe(0, 0).is_none());
+    }
+
+    #[test]
+    fn resize() {
+        let mut tt = TranspositionTable::new(1);
+        tt.store(42, None, 0, 1, Bound::Exact, 0);
+        tt.resize(2);
+        assert_eq!(tt.capacity(), 2 * 1024 * 1024 / 8);
+        assert!(tt.probe(42, 0).is_none());
+    }
+
+    #[test]
+    fn shared_between_threads() {
+        let tt = Arc::new(TranspositionTable::new(1));
+        let handles: Vec<_> = (0..4u64)
+            .map(|t| {
+                let tt = Arc::clone(&tt);
+                thread::spawn(move || {
+                    for i in 0..1000 {
+                        let key = (t << 32 | i).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+                        tt.store(key, None, i as i32, 1, Bound::Exact, 0);
+                        tt.prefetch(key);
+                        if let Some(entry) = tt.probe(key, 0) {
+                            assert_eq!(entry.score, i as i32);
+                        }
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn new_search_from_several_threads() {
+        let tt = Arc::new(TranspositionTable::new(1));
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let tt = Arc::clone(&tt);
+                thread::spawn(move || {
+                    for _ in 0..GENERATION_COUNT as usize * 10 + 1 {
+                        tt.new_search();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        // No increment is lost, so the generation only wrapped around whole times
+        assert_eq!(tt.generation.load(Ordering::Relaxed), 8);
+    }
+}
@@ -6,8 +6,7 @@ use std::ops::{Index, Not};
 /// # Examples
 ///
 /// This is how pieces are defined for the standard variant chess
-///
-
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum PieceType {
     Pawn,
     Knight,
@@ -17,7 +16,9 @@ pub enum PieceType {
     King,
 }
 
-#[derive(Clone, Copy)]
+pub const PIECE_TYPE_COUNT: usize = 6;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Piece {
     WPawn,
     BPawn,
@@ -36,7 +37,47 @@ pub enum Piece {
 
 pub const PIECE_COUNT: usize = 14;
 
-#[derive(Clone, Copy)]
+impl Piece {
+    /// Parse a piece from its FEN character, which is upper case for white
+    pub fn from_char(c: char) -> Option<Piece> {
+        Some(match c {
+            'P' => Piece::WPawn,
+            'p' => Piece::BPawn,
+            'N' => Piece::WKnight,
+            'n' => Piece::BKnight,
+            'B' => Piece::WBishop,
+            'b' => Piece::BBishop,
+            'R' => Piece::WRook,
+            'r' => Piece::BRook,
+            'Q' => Piece::WQueen,
+            'q' => Piece::BQueen,
+            'K' => Piece::WKing,
+            'k' => Piece::BKing,
+            _ => return None,
+        })
+    }
+
+    /// The FEN character of a piece
+    pub fn to_char(self) -> char {
+        match self {
+            Piece::WPawn => 'P',
+            Piece::BPawn => 'p',
+            Piece::WKnight => 'N',
+            Piece::BKnight => 'n',
+            Piece::WBishop => 'B',
+            Piece::BBishop => 'b',
+            Piece::WRook => 'R',
+            Piece::BRook => 'r',
+            Piece::WQueen => 'Q',
+            Piece::BQueen => 'q',
+            Piece::WKing => 'K',
+            Piece::BKing => 'k',
+            Piece::None => panic!("Attempted to obtain the character of None"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ChessSide {
     White,
     Black,
@@ -61,7 +102,7 @@ impl Index<Piece> for [Bitboard; PIECE_COUNT] {
             Piece::BQueen => &self[9],
             Piece::WKing => &self[10],
             Piece::BKing => &self[11],
-            Piece::None => panic!("Attempted to index array with an empty piece")
+            Piece::None => panic!("Attempted to index array with an empty piece"),
         }
     }
 }
@@ -153,7 +194,7 @@ impl From<Piece> for PieceType {
             Piece::BQueen => PieceType::Queen,
             Piece::WKing => PieceType::King,
             Piece::BKing => PieceType::King,
-            Piece::None => panic!("Attempted to obtain the piece type of None")
+            Piece::None => panic!("Attempted to obtain the piece type of None"),
         }
     }
 }
@@ -186,7 +227,7 @@ impl From<Piece> for ChessSide {
             Piece::BRook => ChessSide::Black,
             Piece::BQueen => ChessSide::Black,
             Piece::BKing => ChessSide::Black,
-            Piece::None => panic!("Attempted to obtain the chess side of None")
+            Piece::None => panic!("Attempted to obtain the chess side of None"),
         }
     }
 }
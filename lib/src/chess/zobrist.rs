@@ -0,0 +1,71 @@
+//! Random keys which are xored together to give each position a hash
+
+use super::types::PIECE_COUNT;
+
+pub(super) struct ZobristKeys {
+    pub pieces: [[u64; 64]; PIECE_COUNT],
+    pub side: u64,
+    // One key for every combination of castling rights
+    pub castling: [u64; 16],
+    pub en_passant_file: [u64; 8],
+}
+
+pub(super) static KEYS: ZobristKeys = gen_keys();
+
+/// A step of the splitmix64 generator, which is simple enough to run at compile time
+const fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E3779B97F4A7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+const fn gen_keys() -> ZobristKeys {
+    let mut state = 0x5EED;
+    let mut keys = ZobristKeys {
+        pieces: [[0; 64]; PIECE_COUNT],
+        side: 0,
+        castling: [0; 16],
+        en_passant_file: [0; 8],
+    };
+
+    let mut piece = 0;
+    loop {
+        if piece >= PIECE_COUNT {
+            break;
+        }
+        let mut sq = 0;
+        loop {
+            if sq >= 64 {
+                break;
+            }
+            keys.pieces[piece][sq] = splitmix64(&mut state);
+            sq += 1;
+        }
+        piece += 1;
+    }
+
+    keys.side = splitmix64(&mut state);
+
+    // No castling rights hash to 0, so that a board with nothing on it hashes to 0
+    let mut rights = 1;
+    loop {
+        if rights >= 16 {
+            break;
+        }
+        keys.castling[rights] = splitmix64(&mut state);
+        rights += 1;
+    }
+
+    let mut file = 0;
+    loop {
+        if file >= 8 {
+            break;
+        }
+        keys.en_passant_file[file] = splitmix64(&mut state);
+        file += 1;
+    }
+
+    keys
+}
@@ -7,6 +7,7 @@
 pub use crate::types::*;
 
 pub mod bitboards;
+pub mod movelist;
 mod types;
 
 pub mod chess;
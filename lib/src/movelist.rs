@@ -0,0 +1,170 @@
+//! A fixed-capacity list of moves that lives on the stack, so that generating moves during a
+//! search never has to allocate.
+
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+
+/// The most moves a list can hold. The largest known number of legal moves in a chess position is
+/// 218, so this leaves plenty of room.
+pub const MAX_MOVES: usize = 256;
+
+/// A list of moves with a score slot for each move, which move ordering can fill in
+#[derive(Clone)]
+pub struct MoveList<M> {
+    moves: [M; MAX_MOVES],
+    scores: [i32; MAX_MOVES],
+    len: usize,
+}
+
+impl<M: Copy + Default> MoveList<M> {
+    pub fn new() -> Self {
+        MoveList {
+            moves: [M::default(); MAX_MOVES],
+            scores: [0; MAX_MOVES],
+            len: 0,
+        }
+    }
+
+    /// Add a move to the end of the list, with a score of zero
+    #[inline]
+    pub fn push(&mut self, mv: M) {
+        debug_assert!(self.len < MAX_MOVES, "move list is full");
+        self.moves[self.len] = mv;
+        self.scores[self.len] = 0;
+        self.len += 1;
+    }
+
+    #[inline]
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Keep only the moves for which the predicate returns true, along with their scores
+    pub fn retain(&mut self, mut keep: impl FnMut(&M) -> bool) {
+        let mut kept = 0;
+        for i in 0..self.len {
+            if keep(&self.moves[i]) {
+                self.moves[kept] = self.moves[i];
+                self.scores[kept] = self.scores[i];
+                kept += 1;
+            }
+        }
+        self.len = kept;
+    }
+
+    /// The score of the move at an index
+    #[inline]
+    pub fn score(&self, index: usize) -> i32 {
+        self.scores[..self.len][index]
+    }
+
+    #[inline]
+    pub fn set_score(&mut self, index: usize, score: i32) {
+        self.scores[..self.len][index] = score;
+    }
+
+    /// Swap two moves along with their scores
+    #[inline]
+    pub fn swap_moves(&mut self, a: usize, b: usize) {
+        self.moves[..self.len].swap(a, b);
+        self.scores[..self.len].swap(a, b);
+    }
+
+    /// Move the highest scoring move at or after `start` to `start` and return it. Calling this
+    /// with increasing indices visits the moves from best to worst without sorting the whole list,
+    /// which is cheaper when a cutoff means most of the moves are never looked at.
+    pub fn pick_best(&mut self, start: usize) -> Option<M> {
+        if start >= self.len {
+            return None;
+        }
+        let mut best = start;
+        for i in start + 1..self.len {
+            if self.scores[i] > self.scores[best] {
+                best = i;
+            }
+        }
+        self.swap_moves(start, best);
+        Some(self.moves[start])
+    }
+}
+
+impl<M: Copy + Default> Default for MoveList<M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<M> Deref for MoveList<M> {
+    type Target = [M];
+
+    fn deref(&self) -> &[M] {
+        &self.moves[..self.len]
+    }
+}
+
+impl<M> DerefMut for MoveList<M> {
+    fn deref_mut(&mut self) -> &mut [M] {
+        &mut self.moves[..self.len]
+    }
+}
+
+impl<M> IntoIterator for MoveList<M> {
+    type Item = M;
+    type IntoIter = std::iter::Take<std::array::IntoIter<M, MAX_MOVES>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.moves.into_iter().take(self.len)
+    }
+}
+
+impl<'a, M> IntoIterator for &'a MoveList<M> {
+    type Item = &'a M;
+    type IntoIter = std::slice::Iter<'a, M>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<M: fmt::Debug> fmt::Debug for MoveList<M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_and_iterate() {
+        let mut list = MoveList::new();
+        assert!(list.is_empty());
+        for mv in 0..10u16 {
+            list.push(mv);
+        }
+
+        assert_eq!(list.len(), 10);
+        assert_eq!(list[3], 3);
+        assert!(list.contains(&9));
+        assert_eq!(list.into_iter().sum::<u16>(), 45);
+    }
+
+    #[test]
+    fn pick_best_orders_by_score() {
+        let mut list = MoveList::new();
+        for (mv, score) in [(1u16, 5), (2, -3), (3, 40), (4, 7)] {
+            list.push(mv);
+            let last = list.len() - 1;
+            list.set_score(last, score);
+        }
+
+        let mut picked = Vec::new();
+        let mut i = 0;
+        while let Some(mv) = list.pick_best(i) {
+            picked.push((mv, list.score(i)));
+            i += 1;
+        }
+        assert_eq!(picked, vec![(3, 40), (4, 7), (1, 5), (2, -3)]);
+    }
+}
@@ -1,3 +1,5 @@
+use crate::movelist::MoveList;
+
 /// A variant can be defined as a structure which conforms to the Variant trait.
 /// This trait requires definition of the Board and MoveGen traits.
 /// A variant must be supplied with a name (and also protocol/webui stuff)
@@ -9,17 +11,26 @@ pub trait Variant: Board + MoveGen {
 pub trait Side {}
 
 /// A trait that implements a board state
-pub trait Board {
+pub trait Board: Sized {
     type Move;
+    type FenError;
 
     fn make_move(&mut self, mv: Self::Move);
 
-    fn from_fen(string: String) -> Self;
+    /// Undo a move, which must be the last move that was made on the board
+    fn unmake_move(&mut self, mv: Self::Move);
+
+    fn from_fen(fen: &str) -> Result<Self, Self::FenError>;
+
+    fn to_fen(&self) -> String;
 }
 
 pub trait MoveGen {
-    type Move;
+    type Move: Copy + Default;
+
+    fn gen_quiet(&self) -> MoveList<Self::Move>;
+    fn gen_noisy(&self) -> MoveList<Self::Move>;
 
-    fn gen_quiet(&self) -> Vec<Self::Move>;
-    fn gen_noisy(&self) -> Vec<Self::Move>;
+    /// Generate every legal move in the position
+    fn gen_legal(&self) -> MoveList<Self::Move>;
 }